@@ -3,3 +3,35 @@ pub mod hmtx;
 pub mod cmap;
 pub mod cmap_format;
 pub mod glyf;
+pub mod outline;
+pub mod layout;
+pub mod svg;
+pub mod dxf;
+pub mod toolpath;
+pub mod flatten;
+pub mod report;
+pub mod otlayout;
+pub mod gpos;
+pub mod gsub;
+pub mod features;
+pub mod aat;
+pub mod kerx;
+pub mod trak;
+pub mod morx;
+pub mod fvar;
+pub mod variations;
+pub mod hvar;
+pub mod gvar;
+pub mod name;
+pub mod info;
+pub mod os2;
+pub mod mvar;
+pub mod vmtx;
+pub mod vorg;
+pub mod bidi;
+pub mod joining;
+pub mod indic;
+pub mod gdef;
+pub mod normalize;
+pub mod wrap;
+pub mod hyphenate;
@@ -0,0 +1,126 @@
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom};
+use byteorder::{BigEndian, ReadBytesExt};
+
+//Common structures of the Apple Advanced Typography tables (kerx, morx):
+//lookup tables mapping glyphs to values, and extended state tables driven by glyph classes.
+
+//Glyph classes every extended state table has
+pub const CLASS_END_OF_TEXT: u16 = 0;
+pub const CLASS_OUT_OF_BOUNDS: u16 = 1;
+pub const CLASS_DELETED_GLYPH: u16 = 2;
+
+//Deleted glyphs are marked with 0xFFFF in the glyph run until the run is finished
+pub const DELETED_GLYPH: u32 = 0xFFFF;
+
+pub fn lookup_value(file: &mut File, table_offset: u32, glyph: u32, num_glyphs: u16, value_size: u16) -> io::Result<Option<u32>> {
+    //Reads the value of a glyph from an AAT lookup table, None when the glyph is not listed.
+    //value_size is the size of the values in formats 0, 4 and 8 (2 bytes, 4 for long values).
+    file.seek(SeekFrom::Start(table_offset as u64))?;
+    let format = file.read_u16::<BigEndian>()?;
+
+    match format {
+        0 => {
+            // Simple array, one value for every glyph in the font
+            if glyph >= num_glyphs as u32 {
+                return Ok(None);
+            }
+            file.seek(SeekFrom::Current((glyph * value_size as u32) as i64))?;
+            Ok(Some(read_value(file, value_size)?))
+        }
+        2 | 4 | 6 => {
+            // Binary search header: unitSize, nUnits, searchRange, entrySelector, rangeShift
+            let unit_size = file.read_u16::<BigEndian>()?;
+            let n_units = file.read_u16::<BigEndian>()?;
+            let records_offset = table_offset as u64 + 12;
+            for unit in 0..n_units {
+                file.seek(SeekFrom::Start(records_offset + unit as u64 * unit_size as u64))?;
+                if format == 6 {
+                    // Single table: glyph, value
+                    let unit_glyph = file.read_u16::<BigEndian>()? as u32;
+                    if unit_glyph == glyph {
+                        return Ok(Some(read_value(file, unit_size - 2)?));
+                    }
+                    continue;
+                }
+
+                // Segments: lastGlyph, firstGlyph, then a value (format 2) or an offset to values (format 4)
+                let last_glyph = file.read_u16::<BigEndian>()? as u32;
+                let first_glyph = file.read_u16::<BigEndian>()? as u32;
+                if first_glyph == 0xFFFF || glyph < first_glyph || glyph > last_glyph {
+                    continue;
+                }
+                if format == 2 {
+                    return Ok(Some(read_value(file, unit_size - 4)?));
+                }
+                let values_offset = file.read_u16::<BigEndian>()? as u64;
+                file.seek(SeekFrom::Start(table_offset as u64 + values_offset + (glyph - first_glyph) as u64 * value_size as u64))?;
+                return Ok(Some(read_value(file, value_size)?));
+            }
+            Ok(None)
+        }
+        8 | 10 => {
+            // Trimmed array: firstGlyph, glyphCount, values (format 10 starts with its own unitSize)
+            let unit_size = if format == 10 { file.read_u16::<BigEndian>()? } else { value_size };
+            let first_glyph = file.read_u16::<BigEndian>()? as u32;
+            let glyph_count = file.read_u16::<BigEndian>()? as u32;
+            if glyph < first_glyph || glyph >= first_glyph + glyph_count {
+                return Ok(None);
+            }
+            file.seek(SeekFrom::Current(((glyph - first_glyph) * unit_size as u32) as i64))?;
+            Ok(Some(read_value(file, unit_size)?))
+        }
+        _ => {
+            println!("Debug: Unsupported AAT lookup table format: {}", format);
+            Ok(None)
+        }
+    }
+}
+
+fn read_value(file: &mut File, size: u16) -> io::Result<u32> {
+    match size {
+        1 => Ok(file.read_u8()? as u32),
+        4 => file.read_u32::<BigEndian>(),
+        8 => Ok(file.read_u64::<BigEndian>()? as u32),
+        _ => Ok(file.read_u16::<BigEndian>()? as u32),
+    }
+}
+
+//Extended state table header (STXHeader), all offsets made absolute
+pub struct StateTable {
+    n_classes: u32,
+    class_table: u32,
+    state_array: u32,
+    entry_table: u32,
+    num_glyphs: u16,
+}
+
+impl StateTable {
+    pub fn read(file: &mut File, offset: u32, num_glyphs: u16) -> io::Result<StateTable> {
+        file.seek(SeekFrom::Start(offset as u64))?;
+        let n_classes = file.read_u32::<BigEndian>()?;
+        let class_table = offset + file.read_u32::<BigEndian>()?;
+        let state_array = offset + file.read_u32::<BigEndian>()?;
+        let entry_table = offset + file.read_u32::<BigEndian>()?;
+        Ok(StateTable { n_classes, class_table, state_array, entry_table, num_glyphs })
+    }
+
+    pub fn glyph_class(&self, file: &mut File, glyph: Option<u32>) -> io::Result<u16> {
+        //None is the end of the text
+        match glyph {
+            None => Ok(CLASS_END_OF_TEXT),
+            Some(DELETED_GLYPH) => Ok(CLASS_DELETED_GLYPH),
+            Some(glyph) => Ok(lookup_value(file, self.class_table, glyph, self.num_glyphs, 2)?
+                .map(|class| class as u16)
+                .unwrap_or(CLASS_OUT_OF_BOUNDS)),
+        }
+    }
+
+    pub fn entry_offset(&self, file: &mut File, state: u16, class: u16, entry_size: u32) -> io::Result<u32> {
+        //The state array holds a 16 bit entry index per state and class
+        let class = if (class as u32) < self.n_classes { class } else { CLASS_OUT_OF_BOUNDS };
+        file.seek(SeekFrom::Start(self.state_array as u64 + (state as u64 * self.n_classes as u64 + class as u64) * 2))?;
+        let entry_index = file.read_u16::<BigEndian>()?;
+        Ok(self.entry_table + entry_index as u32 * entry_size)
+    }
+}
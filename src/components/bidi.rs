@@ -0,0 +1,61 @@
+use std::ops::Range;
+use unicode_bidi::BidiInfo;
+use unicode_bidi_mirroring::get_mirrored;
+use crate::components::layout::GlyphAdjustment;
+
+//The Unicode Bidirectional Algorithm (UAX #9) splits mixed Hebrew/Arabic and Latin text into runs
+//of one direction, in the visual order they are printed in. Each run keeps its characters in logical
+//order so GSUB and GPOS see them the way they were typed, right-to-left runs are reversed after shaping.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BidiRun {
+    //The characters of the run in logical order, brackets of right-to-left runs mirrored
+    pub text: String,
+    //Index of the run's first character in the input text
+    pub start: usize,
+    pub rtl: bool,
+}
+
+//A run of the shaped glyphs in visual order: the glyph range and its direction
+pub type GlyphRun = (Range<usize>, bool);
+
+pub fn visual_runs(text: &str) -> Vec<BidiRun> {
+    //Every paragraph is one line here, its runs are returned from left to right
+    let info = BidiInfo::new(text, None);
+    let mut runs = Vec::new();
+    for paragraph in &info.paragraphs {
+        let (levels, level_runs) = info.visual_runs(paragraph, paragraph.range.clone());
+        for range in level_runs {
+            let rtl = levels[range.start].is_rtl();
+            let slice = &text[range.clone()];
+            // Paired brackets and other mirrored characters take their mirror image in right-to-left text
+            let run_text = if rtl {
+                slice.chars().map(|c| get_mirrored(c).unwrap_or(c)).collect()
+            } else {
+                slice.to_string()
+            };
+            runs.push(BidiRun { text: run_text, start: text[..range.start].chars().count(), rtl });
+        }
+    }
+
+    if runs.iter().any(|run| run.rtl) {
+        println!("Debug: Bidi runs in visual order: {:?}", runs);
+    }
+    runs
+}
+
+pub fn visual_adjustments(logical: &[GlyphAdjustment]) -> Vec<GlyphAdjustment> {
+    //Turns the GPOS adjustments of a right-to-left run, made in logical order, into visual order.
+    //An advance adjustment changes the gap after a glyph in logical order, which is the gap to its
+    //left when printed, so it moves to the glyph on the left. The last one stays on the rightmost glyph.
+    let n = logical.len();
+    let mut visual: Vec<GlyphAdjustment> = logical.iter().rev().copied().collect();
+    for adjustment in visual.iter_mut() {
+        adjustment.x_advance = 0;
+    }
+    for (i, adjustment) in logical.iter().enumerate() {
+        let target = if i + 1 < n { n - 2 - i } else { n - 1 };
+        visual[target].x_advance += adjustment.x_advance;
+    }
+    visual
+}
@@ -54,3 +54,9 @@ pub fn parse_cmap(file: &mut File, cmap_offset:u32, input_string: &str) -> io::R
 
     Ok(Vec::new())
 }
+
+pub fn has_glyphs(file: &mut File, cmap_offset: u32, input_string: &str) -> io::Result<bool> {
+    //Whether every character maps to a glyph other than .notdef
+    let glyph_indices = parse_cmap(file, cmap_offset, input_string)?;
+    Ok(glyph_indices.len() == input_string.chars().count() && !glyph_indices.contains(&0))
+}
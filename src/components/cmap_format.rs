@@ -58,6 +58,9 @@ pub fn parse_format4(file: &mut File, input_string: &str) -> io::Result<Vec<u32>
     let _language = file.read_u16::<BigEndian>()?;
     let seg_count_x2 = file.read_u16::<BigEndian>()?;
     let seg_count = seg_count_x2 / 2;
+    let _search_range = file.read_u16::<BigEndian>()?;
+    let _entry_selector = file.read_u16::<BigEndian>()?;
+    let _range_shift = file.read_u16::<BigEndian>()?;
 
     //The format-dependent data is divided into three parts, which must occur in the following order:
     //1. A four-word header gives parameters for an optimized search of the segment list.
@@ -99,7 +102,7 @@ pub fn parse_format4(file: &mut File, input_string: &str) -> io::Result<Vec<u32>
             file,
         ) {
             println!("Debug: {} Mapped to glyph index: {}", ch, glyph_index);
-            glyph_indices.push(glyph_index.try_into().unwrap());
+            glyph_indices.push(glyph_index);
         } else {
             println!("Character '{}' (U+{:04X}) not mapped.", ch, codepoint);
         }
@@ -124,13 +127,14 @@ pub fn map_character_to_glyph(
                 // Use idDelta directly
                 return Some(((codepoint as u32).wrapping_add(id_deltas[i] as u32)) % 65536);
             } else {
-                // Compute glyph index using idRangeOffset
-                let range_offset_pos = glyph_id_array_offset + (i * 2) as u64;
-                file.seek(SeekFrom::Start(range_offset_pos)).ok()?;
-                let range_offset = file.read_u16::<BigEndian>().ok()? as u64;
+                // Compute glyph index using idRangeOffset, the offset is relative to the
+                // position of idRangeOffset[i] itself, which sits just before the glyphIdArray
+                let seg_count = id_range_offsets.len() as u64;
+                let range_offset_pos = glyph_id_array_offset - (seg_count * 2) + (i * 2) as u64;
+                let range_offset = id_range_offsets[i] as u64;
 
                 // Compute glyph array position
-                let glyph_array_pos = glyph_id_array_offset + range_offset + ((codepoint - start) as u64 * 2);
+                let glyph_array_pos = range_offset_pos + range_offset + ((codepoint - start) as u64 * 2);
                 file.seek(SeekFrom::Start(glyph_array_pos)).ok()?;
 
                 // Read glyph ID
@@ -0,0 +1,81 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use crate::components::flatten;
+use crate::components::outline::Segment;
+
+pub fn write_dxf(path: &str, glyphs: &[Vec<Vec<Segment>>], layer: &str, tolerance: f32) -> io::Result<()> {
+    //Writes an AutoCAD R12 (AC1009) DXF, the version every CAM package still reads.
+    //Each glyph contour becomes one closed POLYLINE on the given layer.
+    //The glyph outlines are expected in mm with the baseline at y = 0, DXF is y up as well.
+    let mut out = BufWriter::new(File::create(path)?);
+
+    // HEADER section with the release only. R12 has no unit variables ($INSUNITS and $MEASUREMENT
+    // came with later releases), the coordinates are mm and the importing program is told so.
+    write_group(&mut out, 0, "SECTION")?;
+    write_group(&mut out, 2, "HEADER")?;
+    write_group(&mut out, 9, "$ACADVER")?;
+    write_group(&mut out, 1, "AC1009")?;
+    write_group(&mut out, 0, "ENDSEC")?;
+
+    // TABLES section, declare the layer the contours go on
+    write_group(&mut out, 0, "SECTION")?;
+    write_group(&mut out, 2, "TABLES")?;
+    write_group(&mut out, 0, "TABLE")?;
+    write_group(&mut out, 2, "LAYER")?;
+    write_group(&mut out, 70, "1")?;
+    write_group(&mut out, 0, "LAYER")?;
+    write_group(&mut out, 2, layer)?;
+    write_group(&mut out, 70, "0")?;
+    write_group(&mut out, 62, "7")?;
+    write_group(&mut out, 6, "CONTINUOUS")?;
+    write_group(&mut out, 0, "ENDTAB")?;
+    write_group(&mut out, 0, "ENDSEC")?;
+
+    // ENTITIES section
+    write_group(&mut out, 0, "SECTION")?;
+    write_group(&mut out, 2, "ENTITIES")?;
+
+    let mut contour_count = 0;
+    for contours in glyphs {
+        for contour in contours {
+            //Closed polyline, the closing vertex is implied by flag 1 so the start point is not repeated
+            let vertices = flatten::flatten_contour(contour, tolerance);
+            if vertices.is_empty() {
+                continue;
+            }
+
+            write_group(&mut out, 0, "POLYLINE")?;
+            write_group(&mut out, 8, layer)?;
+            write_group(&mut out, 66, "1")?;
+            write_group(&mut out, 70, "1")?;
+            write_point(&mut out, (0.0, 0.0))?;
+            for vertex in vertices {
+                write_group(&mut out, 0, "VERTEX")?;
+                write_group(&mut out, 8, layer)?;
+                write_point(&mut out, vertex)?;
+            }
+            write_group(&mut out, 0, "SEQEND")?;
+            write_group(&mut out, 8, layer)?;
+            contour_count += 1;
+        }
+    }
+
+    write_group(&mut out, 0, "ENDSEC")?;
+    write_group(&mut out, 0, "EOF")?;
+    out.flush()?;
+
+    println!("DXF written to {} ({} contours on layer {})", path, contour_count, layer);
+    Ok(())
+}
+
+fn write_group(out: &mut impl Write, code: u16, value: &str) -> io::Result<()> {
+    //DXF is a list of group code / value pairs, each on its own line
+    writeln!(out, "{:>3}", code)?;
+    writeln!(out, "{}", value)
+}
+
+fn write_point(out: &mut impl Write, (x, y): (f32, f32)) -> io::Result<()> {
+    write_group(out, 10, &format!("{:.4}", x))?;
+    write_group(out, 20, &format!("{:.4}", y))?;
+    write_group(out, 30, "0.0")
+}
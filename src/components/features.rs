@@ -0,0 +1,74 @@
+use std::io;
+
+//User control over which OpenType features run, given like "liga=0,tnum,smcp,ss02,kern=0".
+//A tag alone or tag=1 turns a feature on, tag=0 turns it off.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeatureSelection {
+    pub script: String,
+    pub language: Option<String>,
+    //Text set in a vertical column, which turns on the vertical alternates
+    pub vertical: bool,
+    settings: Vec<(String, bool)>,
+}
+
+impl FeatureSelection {
+    pub fn parse(features: &str, script: &str, language: Option<&str>) -> io::Result<FeatureSelection> {
+        let mut settings = Vec::new();
+        for item in features.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let (tag, value) = match item.split_once('=') {
+                Some((tag, value)) => {
+                    let value: u32 = value.trim().parse().map_err(|_| {
+                        io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid feature value in '{}'", item))
+                    })?;
+                    (tag.trim(), value != 0)
+                }
+                None => (item, true),
+            };
+            settings.push((to_tag(tag)?, value));
+        }
+
+        Ok(FeatureSelection {
+            script: to_tag(script)?,
+            language: language.map(to_tag).transpose()?,
+            vertical: false,
+            settings,
+        })
+    }
+
+    pub fn is_enabled(&self, tag: &str, default: bool) -> bool {
+        //The last setting of a tag wins, like in CSS font-feature-settings
+        self.settings
+            .iter()
+            .rev()
+            .find(|(t, _)| t == tag)
+            .map(|&(_, on)| on)
+            .unwrap_or(default)
+    }
+
+    pub fn resolve(&self, defaults: &[&str]) -> Vec<String> {
+        //The default features that are not turned off, followed by the extra features turned on
+        let mut tags: Vec<String> = defaults
+            .iter()
+            .filter(|tag| self.is_enabled(tag, true))
+            .map(|tag| tag.to_string())
+            .collect();
+        for (tag, _) in &self.settings {
+            if self.is_enabled(tag, false) && !tags.contains(tag) {
+                tags.push(tag.clone());
+            }
+        }
+        tags
+    }
+}
+
+pub fn to_tag(name: &str) -> io::Result<String> {
+    //OpenType tags are 4 ASCII characters, shorter tags are padded with spaces
+    if name.is_empty() || name.len() > 4 || !name.is_ascii() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("'{}' is not a valid OpenType tag", name),
+        ));
+    }
+    Ok(format!("{:<4}", name))
+}
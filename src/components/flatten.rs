@@ -0,0 +1,72 @@
+use crate::components::outline::Segment;
+
+//Turns curves into straight pieces for outputs and measurements that only know lines.
+//The tolerance is the maximum distance between the curve and its pieces, in the units of the
+//segments. Outlines are placed in mm before flattening, so the tolerance is a physical one:
+//a 5mm letter needs far fewer pieces than a 500mm letter for the same visible smoothness.
+
+pub fn flatten_segment(segment: &Segment, tolerance: f32) -> Vec<(f32, f32)> {
+    //Returns the points after the start point of the segment
+    match *segment {
+        Segment::Line(_, p1) => vec![p1],
+        Segment::Quad(p0, p1, p2) => {
+            //The second derivative of a quadratic is constant, 2(p0 - 2p1 + p2), so sampling in n
+            //equal steps deviates at most |p0 - 2p1 + p2| / (4n^2) from the curve
+            let deviation = length(p0.0 - 2.0 * p1.0 + p2.0, p0.1 - 2.0 * p1.1 + p2.1);
+            let steps = step_count(deviation / (4.0 * tolerance));
+            (1..=steps)
+                .map(|i| {
+                    let t = i as f32 / steps as f32;
+                    let u = 1.0 - t;
+                    (
+                        u * u * p0.0 + 2.0 * u * t * p1.0 + t * t * p2.0,
+                        u * u * p0.1 + 2.0 * u * t * p1.1 + t * t * p2.1,
+                    )
+                })
+                .collect()
+        }
+        Segment::Cubic(p0, p1, p2, p3) => {
+            //The second derivative of a cubic is bounded by 6 max(|p0 - 2p1 + p2|, |p1 - 2p2 + p3|),
+            //so n equal steps deviate at most 3/4 of that over n^2
+            let d1 = length(p0.0 - 2.0 * p1.0 + p2.0, p0.1 - 2.0 * p1.1 + p2.1);
+            let d2 = length(p1.0 - 2.0 * p2.0 + p3.0, p1.1 - 2.0 * p2.1 + p3.1);
+            let steps = step_count(3.0 * d1.max(d2) / (4.0 * tolerance));
+            (1..=steps)
+                .map(|i| {
+                    let t = i as f32 / steps as f32;
+                    let u = 1.0 - t;
+                    (
+                        u * u * u * p0.0 + 3.0 * u * u * t * p1.0 + 3.0 * u * t * t * p2.0 + t * t * t * p3.0,
+                        u * u * u * p0.1 + 3.0 * u * u * t * p1.1 + 3.0 * u * t * t * p2.1 + t * t * t * p3.1,
+                    )
+                })
+                .collect()
+        }
+    }
+}
+
+pub fn flatten_contour(contour: &[Segment], tolerance: f32) -> Vec<(f32, f32)> {
+    //Closed polyline through the contour, the start point is not repeated at the end
+    let mut vertices = Vec::new();
+    let Some(first) = contour.first() else { return vertices };
+    vertices.push(first.start());
+    for segment in contour {
+        vertices.extend(flatten_segment(segment, tolerance));
+    }
+    if vertices.len() > 1 && vertices.first() == vertices.last() {
+        vertices.pop();
+    }
+    vertices
+}
+
+fn length(x: f32, y: f32) -> f32 {
+    (x * x + y * y).sqrt()
+}
+
+fn step_count(steps_squared: f32) -> usize {
+    //A zero or invalid tolerance must not produce an endless or empty subdivision
+    if !steps_squared.is_finite() {
+        return 1;
+    }
+    (steps_squared.sqrt().ceil() as usize).clamp(1, 1024)
+}
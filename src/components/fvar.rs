@@ -0,0 +1,105 @@
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom};
+use byteorder::{BigEndian, ReadBytesExt};
+use crate::components::{name, otlayout};
+
+//The font variations table lists the design axes of a variable font (weight, width, ...)
+//with the range of user values each axis accepts.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariationAxis {
+    pub tag: String,
+    pub min_value: f32,
+    pub default_value: f32,
+    pub max_value: f32,
+    pub name_id: u16,
+}
+
+//A named instance like "SemiBold Condensed": a subfamily name and a user value for every axis
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedInstance {
+    pub subfamily_name_id: u16,
+    pub coordinates: Vec<f32>,
+}
+
+pub fn parse_fvar(file: &mut File, fvar_offset: u32) -> io::Result<Vec<VariationAxis>> {
+    // Header: majorVersion, minorVersion, axesArrayOffset, reserved, axisCount, axisSize,
+    // instanceCount, instanceSize (all 16 bit)
+    file.seek(SeekFrom::Start(fvar_offset as u64))?;
+    let _major_version = file.read_u16::<BigEndian>()?;
+    let _minor_version = file.read_u16::<BigEndian>()?;
+    let axes_array_offset = file.read_u16::<BigEndian>()?;
+    let _reserved = file.read_u16::<BigEndian>()?;
+    let axis_count = file.read_u16::<BigEndian>()?;
+    let axis_size = file.read_u16::<BigEndian>()?;
+
+    // VariationAxisRecord: axisTag, minValue, defaultValue, maxValue (Fixed 16.16), flags, axisNameID
+    let mut axes = Vec::new();
+    for i in 0..axis_count {
+        let record = fvar_offset as u64 + axes_array_offset as u64 + i as u64 * axis_size as u64;
+        file.seek(SeekFrom::Start(record))?;
+        let tag = otlayout::read_tag(file)?;
+        let min_value = read_fixed(file)?;
+        let default_value = read_fixed(file)?;
+        let max_value = read_fixed(file)?;
+        let _flags = file.read_u16::<BigEndian>()?;
+        let name_id = file.read_u16::<BigEndian>()?;
+        axes.push(VariationAxis { tag, min_value, default_value, max_value, name_id });
+    }
+
+    Ok(axes)
+}
+
+pub fn parse_named_instances(file: &mut File, fvar_offset: u32) -> io::Result<Vec<NamedInstance>> {
+    file.seek(SeekFrom::Start(fvar_offset as u64 + 4))?;
+    let axes_array_offset = file.read_u16::<BigEndian>()?;
+    let _reserved = file.read_u16::<BigEndian>()?;
+    let axis_count = file.read_u16::<BigEndian>()?;
+    let axis_size = file.read_u16::<BigEndian>()?;
+    let instance_count = file.read_u16::<BigEndian>()?;
+    let instance_size = file.read_u16::<BigEndian>()?;
+
+    // InstanceRecords follow the axes: subfamilyNameID, flags, coordinates[axisCount] (Fixed 16.16),
+    // and an optional postScriptNameID
+    let instances_start = fvar_offset as u64 + axes_array_offset as u64 + axis_count as u64 * axis_size as u64;
+    let mut instances = Vec::new();
+    for i in 0..instance_count {
+        file.seek(SeekFrom::Start(instances_start + i as u64 * instance_size as u64))?;
+        let subfamily_name_id = file.read_u16::<BigEndian>()?;
+        let _flags = file.read_u16::<BigEndian>()?;
+        let mut coordinates = Vec::new();
+        for _ in 0..axis_count {
+            coordinates.push(read_fixed(file)?);
+        }
+        instances.push(NamedInstance { subfamily_name_id, coordinates });
+    }
+
+    Ok(instances)
+}
+
+pub fn find_named_instance(file: &mut File, fvar_offset: u32, name_offset: u32, instance_name: &str) -> io::Result<Vec<(String, f32)>> {
+    //Resolves an instance name (case is ignored) to axis settings in user units
+    if fvar_offset == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "An instance was given, but the font is not a variable font"));
+    }
+
+    let axes = parse_fvar(file, fvar_offset)?;
+    let mut known = Vec::new();
+    for instance in parse_named_instances(file, fvar_offset)? {
+        let Some(subfamily) = name::get_name(file, name_offset, instance.subfamily_name_id)? else { continue };
+        if subfamily.trim().eq_ignore_ascii_case(instance_name.trim()) {
+            return Ok(axes.iter().map(|axis| axis.tag.clone()).zip(instance.coordinates).collect());
+        }
+        known.push(subfamily);
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("The font has no instance '{}', its instances are: {}", instance_name, known.join(", ")),
+    ))
+}
+
+pub fn read_fixed(file: &mut File) -> io::Result<f32> {
+    //16.16 fixed point number
+    Ok(file.read_i32::<BigEndian>()? as f32 / 65536.0)
+}
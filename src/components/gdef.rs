@@ -0,0 +1,138 @@
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom};
+use byteorder::{BigEndian, ReadBytesExt};
+use crate::components::otlayout::{self, Lookup};
+
+//Glyph classes from the GDEF table: 1 base glyph, 2 ligature, 3 combining mark, 4 ligature component.
+//Marks sit on the glyph before them, so they don't add to the width of the text.
+
+pub const BASE: u16 = 1;
+pub const LIGATURE: u16 = 2;
+pub const MARK: u16 = 3;
+
+//LookupFlag bits that make a lookup look past glyphs of a class when it matches a sequence,
+//so a lam-alef ligature still forms and "T" still kerns with "o" when there is a mark between them
+const IGNORE_BASE_GLYPHS: u16 = 0x0002;
+const IGNORE_LIGATURES: u16 = 0x0004;
+const IGNORE_MARKS: u16 = 0x0008;
+pub const USE_MARK_FILTERING_SET: u16 = 0x0010;
+//The high byte only lets marks of this mark attachment class through
+const MARK_ATTACHMENT_TYPE: u16 = 0xFF00;
+
+pub fn glyph_classes(file: &mut File, gdef_offset: u32, glyph_indices: &[u32]) -> io::Result<Vec<u16>> {
+    //Returns the class of every glyph, 0 for glyphs without one or when the font has no GDEF table
+    if gdef_offset == 0 {
+        return Ok(vec![0; glyph_indices.len()]);
+    }
+    // Header: majorVersion, minorVersion, glyphClassDefOffset
+    file.seek(SeekFrom::Start(gdef_offset as u64 + 4))?;
+    let class_def_offset = file.read_u16::<BigEndian>()?;
+    if class_def_offset == 0 {
+        return Ok(vec![0; glyph_indices.len()]);
+    }
+
+    let mut classes = Vec::with_capacity(glyph_indices.len());
+    for &glyph_index in glyph_indices {
+        classes.push(otlayout::glyph_class(file, gdef_offset + class_def_offset as u32, glyph_index)?);
+    }
+    Ok(classes)
+}
+
+fn glyph_class(file: &mut File, gdef_offset: u32, glyph_index: u32) -> io::Result<u16> {
+    let class_def_offset = otlayout::read_u16_at(file, gdef_offset + 4)?;
+    if class_def_offset == 0 {
+        return Ok(0);
+    }
+    otlayout::glyph_class(file, gdef_offset + class_def_offset as u32, glyph_index)
+}
+
+//The glyphs one lookup skips, from its LookupFlag and the GDEF classes
+pub struct GlyphFilter {
+    gdef_offset: u32,
+    lookup_flag: u16,
+    mark_filtering_set: Option<u16>,
+}
+
+pub fn glyph_filter(gdef_offset: u32, lookup: &Lookup) -> GlyphFilter {
+    GlyphFilter { gdef_offset, lookup_flag: lookup.lookup_flag, mark_filtering_set: lookup.mark_filtering_set }
+}
+
+impl GlyphFilter {
+    pub fn skips(&self, file: &mut File, glyph_index: u32) -> io::Result<bool> {
+        let flags = IGNORE_BASE_GLYPHS | IGNORE_LIGATURES | IGNORE_MARKS | USE_MARK_FILTERING_SET | MARK_ATTACHMENT_TYPE;
+        if self.gdef_offset == 0 || self.lookup_flag & flags == 0 {
+            return Ok(false);
+        }
+        match glyph_class(file, self.gdef_offset, glyph_index)? {
+            BASE => Ok(self.lookup_flag & IGNORE_BASE_GLYPHS != 0),
+            LIGATURE => Ok(self.lookup_flag & IGNORE_LIGATURES != 0),
+            MARK if self.lookup_flag & IGNORE_MARKS != 0 => Ok(true),
+            MARK => {
+                if let Some(set) = self.mark_filtering_set {
+                    return Ok(!in_mark_glyph_set(file, self.gdef_offset, set, glyph_index)?);
+                }
+                let attachment_type = self.lookup_flag >> 8;
+                Ok(attachment_type != 0 && mark_attachment_class(file, self.gdef_offset, glyph_index)? != attachment_type)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    pub fn next(&self, file: &mut File, glyph_indices: &[u32], from: usize, count: usize) -> io::Result<Option<Vec<usize>>> {
+        //The positions of the next count glyphs after from that the lookup doesn't skip
+        let mut positions = Vec::with_capacity(count);
+        for (i, &glyph_index) in glyph_indices.iter().enumerate().skip(from + 1) {
+            if positions.len() == count {
+                break;
+            }
+            if !self.skips(file, glyph_index)? {
+                positions.push(i);
+            }
+        }
+        Ok(Some(positions).filter(|positions| positions.len() == count))
+    }
+
+    pub fn previous(&self, file: &mut File, glyph_indices: &[u32], from: usize, count: usize) -> io::Result<Option<Vec<usize>>> {
+        //The positions of the count glyphs before from that the lookup doesn't skip, nearest first
+        let mut positions = Vec::with_capacity(count);
+        for (i, &glyph_index) in glyph_indices[..from].iter().enumerate().rev() {
+            if positions.len() == count {
+                break;
+            }
+            if !self.skips(file, glyph_index)? {
+                positions.push(i);
+            }
+        }
+        Ok(Some(positions).filter(|positions| positions.len() == count))
+    }
+}
+
+fn mark_attachment_class(file: &mut File, gdef_offset: u32, glyph_index: u32) -> io::Result<u16> {
+    // markAttachClassDefOffset follows glyphClassDef, attachList and ligCaretList
+    let class_def_offset = otlayout::read_u16_at(file, gdef_offset + 10)?;
+    if class_def_offset == 0 {
+        return Ok(0);
+    }
+    otlayout::glyph_class(file, gdef_offset + class_def_offset as u32, glyph_index)
+}
+
+fn in_mark_glyph_set(file: &mut File, gdef_offset: u32, set: u16, glyph_index: u32) -> io::Result<bool> {
+    // markGlyphSetsDefOffset is only in GDEF 1.2 and later, the sets are Coverage tables with 32 bit offsets
+    let minor_version = otlayout::read_u16_at(file, gdef_offset + 2)?;
+    if minor_version < 2 {
+        return Ok(false);
+    }
+    let sets_offset = otlayout::read_u16_at(file, gdef_offset + 12)?;
+    if sets_offset == 0 {
+        return Ok(false);
+    }
+    let sets = gdef_offset + sets_offset as u32;
+    file.seek(SeekFrom::Start(sets as u64 + 2))?;
+    let set_count = file.read_u16::<BigEndian>()?;
+    if set >= set_count {
+        return Ok(false);
+    }
+    file.seek(SeekFrom::Start(sets as u64 + 4 + set as u64 * 4))?;
+    let coverage_offset = sets + file.read_u32::<BigEndian>()?;
+    Ok(otlayout::coverage_index(file, coverage_offset, glyph_index)?.is_some())
+}
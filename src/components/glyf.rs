@@ -2,13 +2,27 @@ use std::fs::File;
 use std::io::{self, Seek, SeekFrom};
 use byteorder::{BigEndian, ReadBytesExt};
 use crate::components::cmap;
+use crate::components::gvar;
+use crate::components::variations::Instance;
+use crate::components::outline::Point;
 
-pub fn get_cap_height(file: &mut File, cmap_offset: u32, glyf_offset: u32, loca_offset: u32, head_offset: u32) -> io::Result<i16> {
-    // Map the character 'H' to its glyph index
-    let glyph_index = cmap::parse_cmap(file, cmap_offset, "H")?;
+pub fn get_cap_height(file: &mut File, cmap_offset: u32, glyf_offset: u32, loca_offset: u32, head_offset: u32, gvar_offset: u32, instance: &Instance) -> io::Result<Option<i16>> {
+    // Map the character 'H' to its glyph index, fonts for scripts without capitals often don't have it
+    let Some(glyph_index) = cmap::parse_cmap(file, cmap_offset, "H")?.first().copied().filter(|&g| g != 0) else {
+        return Ok(None);
+    };
+
+    //At a non-default instance of a variable font the stored bounding box is stale,
+    //measure the outline of 'H' at the instance instead
+    if gvar_offset != 0 && !instance.is_default() {
+        let contours = get_instance_outline(file, glyf_offset, loca_offset, head_offset, gvar_offset, instance, glyph_index)?;
+        if let Some((_, y_min, _, y_max)) = get_glyph_bounds(&contours) {
+            return Ok(Some((y_max - y_min).round() as i16));
+        }
+    }
 
     // Locate the glyph data in the glyf table
-    let glyph_offset = get_glyph_offset(file, loca_offset, glyf_offset, glyph_index, head_offset)?;
+    let glyph_offset = get_glyph_offset(file, loca_offset, glyf_offset, vec![glyph_index], head_offset)?;
 
     // Seek to the glyph data
     file.seek(SeekFrom::Start(glyph_offset as u64))?;
@@ -24,7 +38,7 @@ pub fn get_cap_height(file: &mut File, cmap_offset: u32, glyf_offset: u32, loca_
     //Possible to use OS/2 table to find sChapHeight but it is not always present.
 
     // Return the capital height
-    Ok(y_max - y_min)
+    Ok(Some(y_max - y_min))
 }
 
 pub fn get_glyph_offset(file: &mut File, loca_offset: u32, glyf_offset: u32, glyph_index: Vec<u32>, head_offset: u32) -> io::Result<u32> {
@@ -62,3 +76,295 @@ pub fn check_loca_format(file: &mut File, head_offset: u32) -> io::Result<bool>
     // Return true if 32-bit format, false if 16-bit
     Ok(index_to_loc_format == 1)
 }
+
+pub fn get_glyph_range(file: &mut File, loca_offset: u32, glyph_index: u32, head_offset: u32) -> io::Result<(u32, u32)> {
+    //The length of a glyph is the difference between its loca entry and the next one,
+    //glyphs without outlines like the space have a length of zero
+    let is_loca_32bit = check_loca_format(file, head_offset)?;
+
+    if is_loca_32bit {
+        file.seek(SeekFrom::Start(loca_offset as u64 + (glyph_index as u64 * 4)))?;
+        let start = file.read_u32::<BigEndian>()?;
+        let end = file.read_u32::<BigEndian>()?;
+        Ok((start, end))
+    } else {
+        file.seek(SeekFrom::Start(loca_offset as u64 + (glyph_index as u64 * 2)))?;
+        let start = (file.read_u16::<BigEndian>()? as u32) * 2;
+        let end = (file.read_u16::<BigEndian>()? as u32) * 2;
+        Ok((start, end))
+    }
+}
+
+pub fn get_glyph_outline(file: &mut File, glyf_offset: u32, loca_offset: u32, head_offset: u32, glyph_index: u32) -> io::Result<Vec<Vec<Point>>> {
+    read_glyph_outline(file, glyf_offset, loca_offset, head_offset, None, glyph_index, 0)
+}
+
+pub fn get_instance_outline(file: &mut File, glyf_offset: u32, loca_offset: u32, head_offset: u32, gvar_offset: u32, instance: &Instance, glyph_index: u32) -> io::Result<Vec<Vec<Point>>> {
+    //The outline of a variable font glyph at an instance, the default outline for static fonts
+    let variation = (gvar_offset != 0 && !instance.is_default()).then_some((gvar_offset, instance.coords.as_slice()));
+    read_glyph_outline(file, glyf_offset, loca_offset, head_offset, variation, glyph_index, 0)
+}
+
+pub fn get_glyph_bounds(contours: &[Vec<Point>]) -> Option<(f32, f32, f32, f32)> {
+    //xMin, yMin, xMax, yMax of all points, like the bounding box stored in the glyph header
+    let mut points = contours.iter().flatten();
+    let first = points.next()?;
+    Some(points.fold((first.x, first.y, first.x, first.y), |(x_min, y_min, x_max, y_max), p| {
+        (x_min.min(p.x), y_min.min(p.y), x_max.max(p.x), y_max.max(p.y))
+    }))
+}
+
+//Where glyph variations come from: the gvar table offset and the normalized instance coordinates
+type Variation<'a> = Option<(u32, &'a [f32])>;
+
+fn read_glyph_outline(file: &mut File, glyf_offset: u32, loca_offset: u32, head_offset: u32, variation: Variation, glyph_index: u32, depth: u32) -> io::Result<Vec<Vec<Point>>> {
+    //Composite glyphs may reference other composite glyphs, guard against cycles in broken fonts
+    if depth > 8 {
+        println!("Warning: Composite glyph nesting too deep at glyph {}", glyph_index);
+        return Ok(Vec::new());
+    }
+
+    let (start, end) = get_glyph_range(file, loca_offset, glyph_index, head_offset)?;
+    if end <= start {
+        return Ok(Vec::new());
+    }
+
+    file.seek(SeekFrom::Start(glyf_offset as u64 + start as u64))?;
+    let number_of_contours = file.read_i16::<BigEndian>()?;
+    // Skip the bounding box xMin, yMin, xMax, yMax
+    file.seek(SeekFrom::Current(8))?;
+
+    let Some((gvar_offset, coords)) = variation else {
+        return if number_of_contours >= 0 {
+            read_simple_glyph(file, number_of_contours as u16)
+        } else {
+            read_composite_glyph(file, glyf_offset, loca_offset, head_offset, None, &[], depth)
+        };
+    };
+
+    if number_of_contours >= 0 {
+        // Simple glyphs move every outline point by its delta
+        let mut contours = read_simple_glyph(file, number_of_contours as u16)?;
+        let num_points: usize = contours.iter().map(Vec::len).sum();
+        let deltas = gvar::glyph_deltas(file, gvar_offset, glyph_index, num_points + 4, &contours, coords)?;
+        for (point, delta) in contours.iter_mut().flatten().zip(&deltas) {
+            point.x += delta.0;
+            point.y += delta.1;
+        }
+        Ok(contours)
+    } else {
+        // Composite glyphs have a point per component that moves its offset
+        let component_start = file.stream_position()?;
+        let num_points = get_point_count(file, glyf_offset, loca_offset, head_offset, glyph_index)?;
+        let deltas = gvar::glyph_deltas(file, gvar_offset, glyph_index, num_points + 4, &[], coords)?;
+        file.seek(SeekFrom::Start(component_start))?;
+        read_composite_glyph(file, glyf_offset, loca_offset, head_offset, variation, &deltas, depth)
+    }
+}
+
+fn read_simple_glyph(file: &mut File, number_of_contours: u16) -> io::Result<Vec<Vec<Point>>> {
+    //Simple glyph layout:
+    //endPtsOfContours[numberOfContours], instructionLength, instructions,
+    //flags[], xCoordinates[], yCoordinates[]
+    let mut end_points = Vec::new();
+    for _ in 0..number_of_contours {
+        end_points.push(file.read_u16::<BigEndian>()?);
+    }
+    let num_points = match end_points.last() {
+        Some(&last) => last as usize + 1,
+        None => return Ok(Vec::new()),
+    };
+
+    let instruction_length = file.read_u16::<BigEndian>()?;
+    file.seek(SeekFrom::Current(instruction_length as i64))?;
+
+    // Flags are run length encoded with the REPEAT_FLAG bit (0x08)
+    let mut flags = Vec::with_capacity(num_points);
+    while flags.len() < num_points {
+        let flag = file.read_u8()?;
+        flags.push(flag);
+        if flag & 0x08 != 0 {
+            let repeat = file.read_u8()?;
+            for _ in 0..repeat {
+                flags.push(flag);
+            }
+        }
+    }
+    flags.truncate(num_points);
+
+    // X coordinates: 0x02 short vector, 0x10 positive (short) or same as previous (long)
+    let mut xs = Vec::with_capacity(num_points);
+    let mut x: i32 = 0;
+    for &flag in &flags {
+        if flag & 0x02 != 0 {
+            let dx = file.read_u8()? as i32;
+            x += if flag & 0x10 != 0 { dx } else { -dx };
+        } else if flag & 0x10 == 0 {
+            x += file.read_i16::<BigEndian>()? as i32;
+        }
+        xs.push(x);
+    }
+
+    // Y coordinates: 0x04 short vector, 0x20 positive (short) or same as previous (long)
+    let mut ys = Vec::with_capacity(num_points);
+    let mut y: i32 = 0;
+    for &flag in &flags {
+        if flag & 0x04 != 0 {
+            let dy = file.read_u8()? as i32;
+            y += if flag & 0x20 != 0 { dy } else { -dy };
+        } else if flag & 0x20 == 0 {
+            y += file.read_i16::<BigEndian>()? as i32;
+        }
+        ys.push(y);
+    }
+
+    let mut contours = Vec::new();
+    let mut first = 0;
+    for &end in &end_points {
+        let end = end as usize;
+        if end < first || end >= num_points {
+            break;
+        }
+        let contour = (first..=end)
+            .map(|i| Point { x: xs[i] as f32, y: ys[i] as f32, on_curve: flags[i] & 0x01 != 0 })
+            .collect();
+        contours.push(contour);
+        first = end + 1;
+    }
+
+    Ok(contours)
+}
+
+fn read_composite_glyph(file: &mut File, glyf_offset: u32, loca_offset: u32, head_offset: u32, variation: Variation, offset_deltas: &[(f32, f32)], depth: u32) -> io::Result<Vec<Vec<Point>>> {
+    //A composite glyph is a list of components, each referencing another glyph
+    //with an offset and an optional 2x2 transformation
+    const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+    const ARGS_ARE_XY_VALUES: u16 = 0x0002;
+    const WE_HAVE_A_SCALE: u16 = 0x0008;
+    const MORE_COMPONENTS: u16 = 0x0020;
+    const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+    const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+    let mut contours: Vec<Vec<Point>> = Vec::new();
+    for component_number in 0.. {
+        let flags = file.read_u16::<BigEndian>()?;
+        let component_index = file.read_u16::<BigEndian>()?;
+
+        // The arguments are signed x/y offsets, or unsigned point numbers for point matching
+        let (arg1, arg2) = match (flags & ARG_1_AND_2_ARE_WORDS != 0, flags & ARGS_ARE_XY_VALUES != 0) {
+            (true, true) => (file.read_i16::<BigEndian>()? as i32, file.read_i16::<BigEndian>()? as i32),
+            (true, false) => (file.read_u16::<BigEndian>()? as i32, file.read_u16::<BigEndian>()? as i32),
+            (false, true) => (file.read_i8()? as i32, file.read_i8()? as i32),
+            (false, false) => (file.read_u8()? as i32, file.read_u8()? as i32),
+        };
+
+        // Transformation values are F2Dot14
+        let (mut a, mut b, mut c, mut d) = (1.0f32, 0.0f32, 0.0f32, 1.0f32);
+        if flags & WE_HAVE_A_SCALE != 0 {
+            a = file.read_i16::<BigEndian>()? as f32 / 16384.0;
+            d = a;
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            a = file.read_i16::<BigEndian>()? as f32 / 16384.0;
+            d = file.read_i16::<BigEndian>()? as f32 / 16384.0;
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            a = file.read_i16::<BigEndian>()? as f32 / 16384.0;
+            b = file.read_i16::<BigEndian>()? as f32 / 16384.0;
+            c = file.read_i16::<BigEndian>()? as f32 / 16384.0;
+            d = file.read_i16::<BigEndian>()? as f32 / 16384.0;
+        }
+
+        // Remember where the next component record starts before reading the referenced glyph
+        let next_component = file.stream_position()?;
+        let component = read_glyph_outline(file, glyf_offset, loca_offset, head_offset, variation, component_index as u32, depth + 1)?;
+
+        let (dx, dy) = if flags & ARGS_ARE_XY_VALUES != 0 {
+            let (delta_x, delta_y) = offset_deltas.get(component_number).copied().unwrap_or_default();
+            (arg1 as f32 + delta_x, arg2 as f32 + delta_y)
+        } else {
+            //Point matching: align point arg1 of the glyph so far with point arg2 of the component
+            let parent_point = contours.iter().flatten().nth(arg1 as usize).copied();
+            let child_point = component.iter().flatten().nth(arg2 as usize).copied();
+            match (parent_point, child_point) {
+                (Some(p), Some(q)) => (p.x - (q.x * a + q.y * c), p.y - (q.x * b + q.y * d)),
+                _ => {
+                    println!("Debug: Component {} matches points {} and {} that don't exist, placed at (0, 0)", component_index, arg1, arg2);
+                    (0.0, 0.0)
+                }
+            }
+        };
+
+        for contour in component {
+            contours.push(
+                contour
+                    .iter()
+                    .map(|p| Point {
+                        x: p.x * a + p.y * c + dx,
+                        y: p.x * b + p.y * d + dy,
+                        on_curve: p.on_curve,
+                    })
+                    .collect(),
+            );
+        }
+
+        if flags & MORE_COMPONENTS == 0 {
+            break;
+        }
+        file.seek(SeekFrom::Start(next_component))?;
+    }
+
+    Ok(contours)
+}
+
+pub fn get_glyph_point(file: &mut File, glyf_offset: u32, loca_offset: u32, head_offset: u32, glyph_index: u32, point_index: u16) -> io::Result<Option<(f32, f32)>> {
+    //Outline points are numbered across all contours, this is how AAT control point attachment addresses them
+    let contours = get_glyph_outline(file, glyf_offset, loca_offset, head_offset, glyph_index)?;
+    Ok(contours.iter().flatten().nth(point_index as usize).map(|p| (p.x, p.y)))
+}
+
+pub fn get_point_count(file: &mut File, glyf_offset: u32, loca_offset: u32, head_offset: u32, glyph_index: u32) -> io::Result<usize> {
+    //The number of points gvar numbers its deltas by, without the four phantom points:
+    //the outline points of a simple glyph, one point per component of a composite glyph
+    const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+    const WE_HAVE_A_SCALE: u16 = 0x0008;
+    const MORE_COMPONENTS: u16 = 0x0020;
+    const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+    const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+    let (start, end) = get_glyph_range(file, loca_offset, glyph_index, head_offset)?;
+    if end <= start {
+        return Ok(0);
+    }
+
+    file.seek(SeekFrom::Start(glyf_offset as u64 + start as u64))?;
+    let number_of_contours = file.read_i16::<BigEndian>()?;
+    file.seek(SeekFrom::Current(8))?;
+
+    if number_of_contours >= 0 {
+        if number_of_contours == 0 {
+            return Ok(0);
+        }
+        // The last end point of the contours
+        file.seek(SeekFrom::Current((number_of_contours as i64 - 1) * 2))?;
+        return Ok(file.read_u16::<BigEndian>()? as usize + 1);
+    }
+
+    let mut components = 0;
+    loop {
+        let flags = file.read_u16::<BigEndian>()?;
+        let _component_index = file.read_u16::<BigEndian>()?;
+        let mut skip = if flags & ARG_1_AND_2_ARE_WORDS != 0 { 4 } else { 2 };
+        if flags & WE_HAVE_A_SCALE != 0 {
+            skip += 2;
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            skip += 4;
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            skip += 8;
+        }
+        file.seek(SeekFrom::Current(skip))?;
+        components += 1;
+        if flags & MORE_COMPONENTS == 0 {
+            break;
+        }
+    }
+    Ok(components)
+}
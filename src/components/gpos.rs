@@ -0,0 +1,422 @@
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom};
+use byteorder::{BigEndian, ReadBytesExt};
+use crate::components::features::FeatureSelection;
+use crate::components::gdef::{self, GlyphFilter};
+use crate::components::layout::GlyphAdjustment;
+use crate::components::otlayout;
+use crate::components::variations::{self, Instance};
+
+//GPOS lookup types used here
+const SINGLE_ADJUSTMENT: u16 = 1;
+const PAIR_ADJUSTMENT: u16 = 2;
+const CURSIVE_ATTACHMENT: u16 = 3;
+const MARK_TO_BASE: u16 = 4;
+const MARK_TO_MARK: u16 = 6;
+const EXTENSION_POSITIONING: u16 = 9;
+
+//Positioning features that are on unless turned off
+pub const DEFAULT_FEATURES: [&str; 4] = ["kern", "curs", "mark", "mkmk"];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ValueRecord {
+    pub x_placement: i16,
+    pub y_placement: i16,
+    pub x_advance: i16,
+}
+
+pub fn has_kerning(file: &mut File, gpos_offset: u32, selection: &FeatureSelection) -> io::Result<bool> {
+    //Fonts with a GPOS 'kern' feature keep their kerning there, the legacy kern table is then ignored
+    let kern = ["kern".to_string()];
+    let lookups = otlayout::find_feature_lookups(file, gpos_offset, &selection.script, selection.language.as_deref(), &kern)?;
+    Ok(!lookups.is_empty())
+}
+
+//A run of glyphs in logical order with their advances and GDEF classes,
+//and the GDEF table the lookup flags look up the glyphs to skip in
+pub struct GposRun<'a> {
+    pub glyph_indices: &'a [u32],
+    pub advance_widths: &'a [u16],
+    pub classes: &'a [u16],
+    pub gdef_offset: u32,
+    pub rtl: bool,
+}
+
+pub fn apply_gpos(file: &mut File, gpos_offset: u32, run: &GposRun, selection: &FeatureSelection, instance: &Instance) -> io::Result<Vec<GlyphAdjustment>> {
+    //Applies the single adjustment, pair adjustment, cursive attachment and mark attachment lookups
+    //of the selected features to the glyph run. Pairs, cursive connections and stacked marks are matched
+    //across the glyphs a lookup skips by its lookup flag, like the marks between two kerned letters.
+    //Returns one adjustment per glyph.
+    let glyph_indices = run.glyph_indices;
+    let mut adjustments = vec![GlyphAdjustment::default(); glyph_indices.len()];
+    let mut attachments = vec![None; glyph_indices.len()];
+
+    let feature_tags = selection.resolve(&DEFAULT_FEATURES);
+    let lookup_indices = otlayout::find_feature_lookups(file, gpos_offset, &selection.script, selection.language.as_deref(), &feature_tags)?;
+    for lookup_index in lookup_indices {
+        let lookup = otlayout::read_lookup(file, gpos_offset, lookup_index, EXTENSION_POSITIONING)?;
+        let filter = gdef::glyph_filter(run.gdef_offset, &lookup);
+        match lookup.lookup_type {
+            SINGLE_ADJUSTMENT => {
+                for (i, &glyph) in glyph_indices.iter().enumerate() {
+                    if filter.skips(file, glyph)? {
+                        continue;
+                    }
+                    for &subtable in &lookup.subtables {
+                        if let Some(value) = apply_single_adjustment(file, subtable, glyph, instance)? {
+                            adjustments[i].add(&value);
+                            break;
+                        }
+                    }
+                }
+            }
+            PAIR_ADJUSTMENT => apply_pair_lookup(file, &lookup.subtables, &filter, glyph_indices, instance, &mut adjustments)?,
+            CURSIVE_ATTACHMENT => apply_cursive_lookup(file, &lookup.subtables, &filter, run, &mut adjustments)?,
+            MARK_TO_BASE | MARK_TO_MARK => apply_mark_lookup(file, &lookup.subtables, lookup.lookup_type, &filter, run, &mut attachments)?,
+            _ => {}
+        }
+    }
+    place_marks(run, &attachments, &mut adjustments);
+
+    Ok(adjustments)
+}
+
+fn apply_pair_lookup(file: &mut File, subtables: &[u32], filter: &GlyphFilter, glyph_indices: &[u32], instance: &Instance, adjustments: &mut [GlyphAdjustment]) -> io::Result<()> {
+    let mut i = 0;
+    while i + 1 < glyph_indices.len() {
+        if filter.skips(file, glyph_indices[i])? {
+            i += 1;
+            continue;
+        }
+        let Some(next) = filter.next(file, glyph_indices, i, 1)? else { break };
+        let j = next[0];
+        let first = glyph_indices[i];
+        let second = glyph_indices[j];
+
+        let mut matched = None;
+        for &subtable in subtables {
+            matched = apply_pair_adjustment(file, subtable, first, second, instance)?;
+            if matched.is_some() {
+                break;
+            }
+        }
+
+        match matched {
+            Some((value1, value2, value_format2)) => {
+                if value1 != ValueRecord::default() {
+                    println!(
+                        "Debug: GPOS pair ({}, {}) has adjustment: {}",
+                        first, second, value1.x_advance
+                    );
+                }
+                adjustments[i].add(&value1);
+                adjustments[j].add(&value2);
+                //When the second glyph got a value record too, it is consumed by this pair
+                i = if value_format2 != 0 { j + 1 } else { j };
+            }
+            None => i = j,
+        }
+    }
+    Ok(())
+}
+
+fn apply_cursive_lookup(file: &mut File, subtables: &[u32], filter: &GlyphFilter, run: &GposRun, adjustments: &mut [GlyphAdjustment]) -> io::Result<()> {
+    //Joins the exit anchor of every glyph to the entry anchor of the next one in logical order,
+    //by changing the advance between them and moving the next glyph up or down.
+    //Skipped glyphs between the two (vowel marks) keep their advances.
+    let (glyph_indices, advance_widths) = (run.glyph_indices, run.advance_widths);
+    for i in 0..glyph_indices.len().saturating_sub(1) {
+        if filter.skips(file, glyph_indices[i])? {
+            continue;
+        }
+        let Some(next) = filter.next(file, glyph_indices, i, 1)? else { break };
+        let j = next[0];
+        let mut exit = None;
+        let mut entry = None;
+        for &subtable in subtables {
+            if let Some((_, exit_anchor)) = read_entry_exit(file, subtable, glyph_indices[i])? {
+                exit = exit_anchor;
+                break;
+            }
+        }
+        for &subtable in subtables {
+            if let Some((entry_anchor, _)) = read_entry_exit(file, subtable, glyph_indices[j])? {
+                entry = entry_anchor;
+                break;
+            }
+        }
+        let (Some((exit_x, exit_y)), Some((entry_x, entry_y))) = (exit, entry) else { continue };
+
+        let advance = |k: usize| advance_widths.get(k).copied().unwrap_or(0) as i32;
+        let between: i32 = (i + 1..j).map(|k| advance(k) + adjustments[k].x_advance).sum();
+        // Left-to-right the next glyph is on the right, right-to-left it is printed on the left
+        adjustments[i].x_advance = if run.rtl {
+            entry_x as i32 - exit_x as i32 - advance(j) - between
+        } else {
+            exit_x as i32 - entry_x as i32 - advance(i) - between
+        };
+        adjustments[j].y_placement = adjustments[i].y_placement + exit_y as i32 - entry_y as i32;
+        println!(
+            "Debug: GPOS cursive attachment ({}, {}) advance: {}",
+            glyph_indices[i], glyph_indices[j], adjustments[i].x_advance
+        );
+    }
+    Ok(())
+}
+
+//A mark attached to an earlier glyph, with the offset from the glyph's anchor to the mark's anchor
+type Attachment = (usize, i32, i32);
+
+fn apply_mark_lookup(file: &mut File, subtables: &[u32], lookup_type: u16, filter: &GlyphFilter, run: &GposRun, attachments: &mut [Option<Attachment>]) -> io::Result<()> {
+    //Attaches every mark to the glyph it belongs to: the base glyph before it (skipping other marks)
+    //for mark-to-base, the mark before it that the lookup doesn't skip for mark-to-mark. A mark stacked
+    //on another mark stays there, whichever of the two lookups comes first.
+    let is_mark = |i: usize| run.classes.get(i) == Some(&gdef::MARK);
+    for mark in (0..run.glyph_indices.len()).filter(|&i| is_mark(i)) {
+        if filter.skips(file, run.glyph_indices[mark])? {
+            continue;
+        }
+        let base = if lookup_type == MARK_TO_MARK {
+            filter.previous(file, run.glyph_indices, mark, 1)?.map(|previous| previous[0]).filter(|&i| is_mark(i))
+        } else if attachments[mark].is_some_and(|(base, _, _)| is_mark(base)) {
+            None
+        } else {
+            (0..mark).rev().find(|&i| !is_mark(i))
+        };
+        let Some(base) = base else { continue };
+
+        for &subtable in subtables {
+            if let Some(((base_x, base_y), (mark_x, mark_y))) = read_mark_anchors(file, subtable, run.glyph_indices[mark], run.glyph_indices[base])? {
+                attachments[mark] = Some((base, base_x as i32 - mark_x as i32, base_y as i32 - mark_y as i32));
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn place_marks(run: &GposRun, attachments: &[Option<Attachment>], adjustments: &mut [GlyphAdjustment]) {
+    //Moves every attached mark onto its anchor, once all advances are known.
+    //Glyphs are placed in order, so a mark on a mark follows where that mark went.
+    let advance = |i: usize| run.advance_widths.get(i).copied().unwrap_or(0) as i32;
+    for (mark, attachment) in attachments.iter().enumerate() {
+        let Some((base, dx, dy)) = *attachment else { continue };
+        // The distance from the pen at the base to the pen at the mark. Right-to-left the mark is printed
+        // left of the base, with the advances in between (the mark's own included) and the gaps after them.
+        let gaps: i32 = adjustments[base..mark].iter().map(|a| a.x_advance).sum();
+        let pen_distance = if run.rtl {
+            -((base + 1..=mark).map(advance).sum::<i32>() + gaps)
+        } else {
+            (base..mark).map(advance).sum::<i32>() + gaps
+        };
+        adjustments[mark].x_placement = adjustments[base].x_placement + dx - pen_distance;
+        adjustments[mark].y_placement = adjustments[base].y_placement + dy;
+        println!(
+            "Debug: GPOS mark {} attached to {} at: ({}, {})",
+            run.glyph_indices[mark], run.glyph_indices[base], adjustments[mark].x_placement, adjustments[mark].y_placement
+        );
+    }
+}
+
+fn read_mark_anchors(file: &mut File, subtable: u32, mark: u32, base: u32) -> io::Result<Option<(Anchor, Anchor)>> {
+    // MarkBasePosFormat1 and MarkMarkPosFormat1 share one layout: markCoverage, baseCoverage, markClassCount,
+    // markArray and baseArray. The MarkArray gives every mark its class and anchor, the BaseArray
+    // an anchor per mark class for every base. Returns the base anchor and the mark anchor.
+    file.seek(SeekFrom::Start(subtable as u64))?;
+    let format = file.read_u16::<BigEndian>()?;
+    let mark_coverage_offset = subtable + file.read_u16::<BigEndian>()? as u32;
+    let base_coverage_offset = subtable + file.read_u16::<BigEndian>()? as u32;
+    let mark_class_count = file.read_u16::<BigEndian>()?;
+    let mark_array_offset = subtable + file.read_u16::<BigEndian>()? as u32;
+    let base_array_offset = subtable + file.read_u16::<BigEndian>()? as u32;
+    if format != 1 {
+        return Ok(None);
+    }
+    let Some(mark_coverage) = otlayout::coverage_index(file, mark_coverage_offset, mark)? else { return Ok(None) };
+    let Some(base_coverage) = otlayout::coverage_index(file, base_coverage_offset, base)? else { return Ok(None) };
+
+    file.seek(SeekFrom::Start(mark_array_offset as u64 + 2 + mark_coverage as u64 * 4))?;
+    let mark_class = file.read_u16::<BigEndian>()?;
+    let mark_anchor_offset = file.read_u16::<BigEndian>()?;
+    if mark_class >= mark_class_count {
+        return Ok(None);
+    }
+    let base_record = base_coverage as u64 * mark_class_count as u64 + mark_class as u64;
+    file.seek(SeekFrom::Start(base_array_offset as u64 + 2 + base_record * 2))?;
+    let base_anchor_offset = file.read_u16::<BigEndian>()?;
+
+    let base_anchor = read_anchor(file, base_array_offset, base_anchor_offset)?;
+    let mark_anchor = read_anchor(file, mark_array_offset, mark_anchor_offset)?;
+    Ok(base_anchor.zip(mark_anchor))
+}
+
+type Anchor = (i16, i16);
+
+fn read_entry_exit(file: &mut File, subtable: u32, glyph: u32) -> io::Result<Option<(Option<Anchor>, Option<Anchor>)>> {
+    // CursivePosFormat1: coverage, entryExitCount, then an entry and an exit anchor offset per covered glyph
+    file.seek(SeekFrom::Start(subtable as u64))?;
+    let format = file.read_u16::<BigEndian>()?;
+    let coverage_offset = subtable + file.read_u16::<BigEndian>()? as u32;
+    let entry_exit_count = file.read_u16::<BigEndian>()?;
+    if format != 1 {
+        return Ok(None);
+    }
+    let Some(coverage) = otlayout::coverage_index(file, coverage_offset, glyph)? else { return Ok(None) };
+    if coverage >= entry_exit_count {
+        return Ok(None);
+    }
+    file.seek(SeekFrom::Start(subtable as u64 + 6 + coverage as u64 * 4))?;
+    let entry_offset = file.read_u16::<BigEndian>()?;
+    let exit_offset = file.read_u16::<BigEndian>()?;
+    let entry = read_anchor(file, subtable, entry_offset)?;
+    let exit = read_anchor(file, subtable, exit_offset)?;
+    Ok(Some((entry, exit)))
+}
+
+fn read_anchor(file: &mut File, base: u32, anchor_offset: u16) -> io::Result<Option<Anchor>> {
+    //All three anchor formats start with the x and y coordinate,
+    //the contour point and device tables of formats 2 and 3 are only for hinting
+    if anchor_offset == 0 {
+        return Ok(None);
+    }
+    file.seek(SeekFrom::Start(base as u64 + anchor_offset as u64 + 2))?;
+    let x = file.read_i16::<BigEndian>()?;
+    let y = file.read_i16::<BigEndian>()?;
+    Ok(Some((x, y)))
+}
+
+fn apply_single_adjustment(file: &mut File, subtable: u32, glyph: u32, instance: &Instance) -> io::Result<Option<ValueRecord>> {
+    file.seek(SeekFrom::Start(subtable as u64))?;
+    let format = file.read_u16::<BigEndian>()?;
+    let coverage_offset = subtable + file.read_u16::<BigEndian>()? as u32;
+    let value_format = file.read_u16::<BigEndian>()?;
+    let Some(coverage) = otlayout::coverage_index(file, coverage_offset, glyph)? else { return Ok(None) };
+
+    match format {
+        // Format 1: one value record for every covered glyph
+        1 => file.seek(SeekFrom::Start(subtable as u64 + 6))?,
+        // Format 2: a value record per covered glyph after valueCount
+        2 => file.seek(SeekFrom::Start(subtable as u64 + 8 + coverage as u64 * value_record_size(value_format)))?,
+        _ => return Ok(None),
+    };
+    Ok(Some(read_value_record(file, value_format, subtable, instance)?))
+}
+
+fn apply_pair_adjustment(file: &mut File, subtable: u32, first: u32, second: u32, instance: &Instance) -> io::Result<Option<(ValueRecord, ValueRecord, u16)>> {
+    file.seek(SeekFrom::Start(subtable as u64))?;
+    let format = file.read_u16::<BigEndian>()?;
+    let coverage_offset = subtable + file.read_u16::<BigEndian>()? as u32;
+    let value_format1 = file.read_u16::<BigEndian>()?;
+    let value_format2 = file.read_u16::<BigEndian>()?;
+
+    match format {
+        1 => {
+            // Format 1: a PairSet per covered first glyph, listing second glyphs and their values
+            let pair_set_count = file.read_u16::<BigEndian>()?;
+            let Some(coverage) = otlayout::coverage_index(file, coverage_offset, first)? else { return Ok(None) };
+            if coverage >= pair_set_count {
+                return Ok(None);
+            }
+            file.seek(SeekFrom::Start(subtable as u64 + 10 + coverage as u64 * 2))?;
+            let pair_set_offset = subtable + file.read_u16::<BigEndian>()? as u32;
+
+            file.seek(SeekFrom::Start(pair_set_offset as u64))?;
+            let pair_value_count = file.read_u16::<BigEndian>()?;
+            let record_size = 2 + value_record_size(value_format1) + value_record_size(value_format2);
+            for k in 0..pair_value_count {
+                file.seek(SeekFrom::Start(pair_set_offset as u64 + 2 + k as u64 * record_size))?;
+                let second_glyph = file.read_u16::<BigEndian>()? as u32;
+                if second_glyph == second {
+                    let value1 = read_value_record(file, value_format1, subtable, instance)?;
+                    let value2 = read_value_record(file, value_format2, subtable, instance)?;
+                    return Ok(Some((value1, value2, value_format2)));
+                }
+            }
+            Ok(None)
+        }
+        2 => {
+            // Format 2: values from a class1 x class2 matrix, classes from two ClassDef tables
+            let class_def1_offset = subtable + file.read_u16::<BigEndian>()? as u32;
+            let class_def2_offset = subtable + file.read_u16::<BigEndian>()? as u32;
+            let class1_count = file.read_u16::<BigEndian>()?;
+            let class2_count = file.read_u16::<BigEndian>()?;
+            let records_offset = subtable as u64 + 16;
+
+            if otlayout::coverage_index(file, coverage_offset, first)?.is_none() {
+                return Ok(None);
+            }
+            let class1 = otlayout::glyph_class(file, class_def1_offset, first)?;
+            let class2 = otlayout::glyph_class(file, class_def2_offset, second)?;
+            if class1 >= class1_count || class2 >= class2_count {
+                return Ok(None);
+            }
+
+            let record_size = value_record_size(value_format1) + value_record_size(value_format2);
+            let index = class1 as u64 * class2_count as u64 + class2 as u64;
+            file.seek(SeekFrom::Start(records_offset + index * record_size))?;
+            let value1 = read_value_record(file, value_format1, subtable, instance)?;
+            let value2 = read_value_record(file, value_format2, subtable, instance)?;
+            Ok(Some((value1, value2, value_format2)))
+        }
+        _ => {
+            println!("Debug: Unsupported PairPos format: {}", format);
+            Ok(None)
+        }
+    }
+}
+
+fn value_record_size(value_format: u16) -> u64 {
+    //Every set bit of the value format adds one 16 bit field
+    (value_format & 0xFF).count_ones() as u64 * 2
+}
+
+pub fn read_value_record(file: &mut File, value_format: u16, subtable: u32, instance: &Instance) -> io::Result<ValueRecord> {
+    //Fields appear in bit order: XPlacement, YPlacement, XAdvance, YAdvance,
+    //then the four Device table offsets (from the start of the subtable).
+    //Device tables only matter for hinting at a ppem size, unless they are VariationIndex
+    //tables pointing at the deltas of a variable font in the GDEF ItemVariationStore.
+    let mut value = ValueRecord::default();
+    if value_format & 0x0001 != 0 {
+        value.x_placement = file.read_i16::<BigEndian>()?;
+    }
+    if value_format & 0x0002 != 0 {
+        value.y_placement = file.read_i16::<BigEndian>()?;
+    }
+    if value_format & 0x0004 != 0 {
+        value.x_advance = file.read_i16::<BigEndian>()?;
+    }
+    if value_format & 0x0008 != 0 {
+        let _y_advance = file.read_i16::<BigEndian>()?;
+    }
+    let mut devices = [0u16; 4];
+    for (k, bit) in [0x0010, 0x0020, 0x0040, 0x0080].into_iter().enumerate() {
+        if value_format & bit != 0 {
+            devices[k] = file.read_u16::<BigEndian>()?;
+        }
+    }
+
+    if instance.gdef_var_store != 0 && !instance.is_default() {
+        let position = file.stream_position()?;
+        value.x_placement += variation_delta(file, subtable, devices[0], instance)?;
+        value.y_placement += variation_delta(file, subtable, devices[1], instance)?;
+        value.x_advance += variation_delta(file, subtable, devices[2], instance)?;
+        file.seek(SeekFrom::Start(position))?;
+    }
+    Ok(value)
+}
+
+fn variation_delta(file: &mut File, subtable: u32, device_offset: u16, instance: &Instance) -> io::Result<i16> {
+    // VariationIndex table: deltaSetOuterIndex, deltaSetInnerIndex, deltaFormat 0x8000
+    if device_offset == 0 {
+        return Ok(0);
+    }
+    file.seek(SeekFrom::Start(subtable as u64 + device_offset as u64))?;
+    let outer = file.read_u16::<BigEndian>()?;
+    let inner = file.read_u16::<BigEndian>()?;
+    let delta_format = file.read_u16::<BigEndian>()?;
+    if delta_format != 0x8000 {
+        return Ok(0);
+    }
+    let delta = variations::item_delta(file, instance.gdef_var_store, outer, inner, &instance.coords)?;
+    Ok(delta.round() as i16)
+}
@@ -0,0 +1,462 @@
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom};
+use byteorder::{BigEndian, ReadBytesExt};
+use crate::components::features::FeatureSelection;
+use crate::components::gdef::{self, GlyphFilter};
+use crate::components::joining::FORM_FEATURES;
+use crate::components::otlayout::{self, read_u16_array, read_u16_at};
+
+//GSUB lookup types
+const SINGLE: u16 = 1;
+const MULTIPLE: u16 = 2;
+const ALTERNATE: u16 = 3;
+const LIGATURE: u16 = 4;
+const CONTEXT: u16 = 5;
+const CHAINING_CONTEXT: u16 = 6;
+const EXTENSION_SUBSTITUTION: u16 = 7;
+
+//Features a browser or InDesign turns on for horizontal text without asking
+pub const DEFAULT_FEATURES: [&str; 6] = ["ccmp", "locl", "rlig", "liga", "clig", "calt"];
+//Added to the defaults for vertical text: vertical alternates and rotation
+pub const VERTICAL_FEATURES: [&str; 2] = ["vert", "vrt2"];
+
+//Contextual lookups call other lookups, which may be contextual again
+const MAX_NESTING: u32 = 8;
+
+//A glyph of the run and the index of the input glyph it came from.
+//Ligatures keep the cluster of their first component, multiple substitutions repeat it.
+pub type RunGlyph = (u32, usize);
+
+//A sequence lookup record: apply lookup_index at sequence_index of the matched input
+type LookupRecord = (u16, u16);
+
+pub fn apply_gsub(file: &mut File, gsub_offset: u32, gdef_offset: u32, glyph_indices: &[u32], selection: &FeatureSelection, forms: &[Option<&str>]) -> io::Result<(Vec<u32>, Vec<usize>)> {
+    //Runs the lookups of the requested features over the glyph run in LookupList order.
+    //forms holds the joining form feature of every input glyph for cursive scripts, and is empty otherwise:
+    //then ccmp and locl run first, each form feature only at its glyphs, and the other features after.
+    //Returns the new glyph run and for every glyph the index of the input glyph it started as.
+    let mut run: Vec<RunGlyph> = glyph_indices.iter().enumerate().map(|(i, &g)| (g, i)).collect();
+    let feature_tags = resolve_features(selection);
+
+    if forms.iter().any(Option::is_some) {
+        let (early, late): (Vec<String>, Vec<String>) = feature_tags.into_iter().partition(|tag| tag == "ccmp" || tag == "locl");
+        apply_features(file, gsub_offset, gdef_offset, selection, &early, &mut run, None)?;
+        for form in FORM_FEATURES {
+            if selection.is_enabled(form, true) {
+                let mask: Vec<bool> = forms.iter().map(|&f| f == Some(form)).collect();
+                apply_features(file, gsub_offset, gdef_offset, selection, &[form.to_string()], &mut run, Some(&mask))?;
+            }
+        }
+        apply_features(file, gsub_offset, gdef_offset, selection, &late, &mut run, None)?;
+    } else {
+        apply_features(file, gsub_offset, gdef_offset, selection, &feature_tags, &mut run, None)?;
+    }
+
+    Ok(split_run(glyph_indices, &run))
+}
+
+pub fn resolve_features(selection: &FeatureSelection) -> Vec<String> {
+    //The default features (with the vertical alternates for vertical text) and the ones the user turned on
+    let mut defaults = DEFAULT_FEATURES.to_vec();
+    if selection.vertical {
+        defaults.extend(VERTICAL_FEATURES);
+    }
+    selection.resolve(&defaults)
+}
+
+pub fn split_run(glyph_indices: &[u32], run: &[RunGlyph]) -> (Vec<u32>, Vec<usize>) {
+    let glyphs: Vec<u32> = run.iter().map(|&(g, _)| g).collect();
+    if glyphs != glyph_indices {
+        println!("Debug: GSUB changed the glyph run {:?} -> {:?}", glyph_indices, glyphs);
+    }
+    let clusters = run.iter().map(|&(_, c)| c).collect();
+    (glyphs, clusters)
+}
+
+pub fn apply_features(file: &mut File, gsub_offset: u32, gdef_offset: u32, selection: &FeatureSelection, feature_tags: &[String], run: &mut Vec<RunGlyph>, mask: Option<&[bool]>) -> io::Result<()> {
+    //Applies the lookups of the features to the run. With a mask (indexed by the input glyph a glyph came from)
+    //the lookups only start at the glyphs whose entry is true.
+    let lookup_indices = otlayout::find_feature_lookups(file, gsub_offset, &selection.script, selection.language.as_deref(), feature_tags)?;
+    for lookup_index in lookup_indices {
+        let mut i = 0;
+        while i < run.len() {
+            if mask.is_some_and(|mask| !mask.get(run[i].1).copied().unwrap_or(false)) {
+                i += 1;
+                continue;
+            }
+            let length_before = run.len();
+            match apply_lookup(file, gsub_offset, gdef_offset, lookup_index, run, i, 0)? {
+                // A deletion leaves the position on the next glyph, anything else moves past the match
+                Some(next) if run.len() < length_before => i = next,
+                Some(next) => i = next.max(i + 1),
+                None => i += 1,
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn would_substitute(file: &mut File, gsub_offset: u32, gdef_offset: u32, selection: &FeatureSelection, feature_tag: &str, glyphs: &[u32]) -> io::Result<bool> {
+    //Whether the feature changes this glyph sequence, used to ask the font which forms it has
+    let mut run: Vec<RunGlyph> = glyphs.iter().enumerate().map(|(i, &g)| (g, i)).collect();
+    apply_features(file, gsub_offset, gdef_offset, selection, &[feature_tag.to_string()], &mut run, None)?;
+    Ok(run.iter().map(|&(g, _)| g).ne(glyphs.iter().copied()))
+}
+
+fn apply_lookup(file: &mut File, gsub_offset: u32, gdef_offset: u32, lookup_index: u16, run: &mut Vec<RunGlyph>, pos: usize, depth: u32) -> io::Result<Option<usize>> {
+    //Tries the subtables of one lookup at pos, the first one that applies wins.
+    //Returns the position after the substituted glyphs.
+    //Glyphs the lookup flag skips (marks for most Arabic lookups) are not substituted and not matched.
+    let lookup = otlayout::read_lookup(file, gsub_offset, lookup_index, EXTENSION_SUBSTITUTION)?;
+    let filter = gdef::glyph_filter(gdef_offset, &lookup);
+    if filter.skips(file, run[pos].0)? {
+        return Ok(None);
+    }
+    for &subtable in &lookup.subtables {
+        let applied = match lookup.lookup_type {
+            SINGLE => apply_single(file, subtable, run, pos)?,
+            MULTIPLE => apply_multiple(file, subtable, run, pos)?,
+            ALTERNATE => apply_alternate(file, subtable, run, pos)?,
+            LIGATURE => apply_ligature(file, subtable, &filter, run, pos)?,
+            CONTEXT | CHAINING_CONTEXT => {
+                let records = if lookup.lookup_type == CONTEXT {
+                    match_context(file, subtable, &filter, run, pos)?
+                } else {
+                    match_chaining_context(file, subtable, &filter, run, pos)?
+                };
+                match records {
+                    Some((input_positions, records)) => {
+                        Some(apply_lookup_records(file, gsub_offset, gdef_offset, run, input_positions, &records, depth)?)
+                    }
+                    None => None,
+                }
+            }
+            _ => None,
+        };
+        if applied.is_some() {
+            return Ok(applied);
+        }
+    }
+    Ok(None)
+}
+
+fn apply_single(file: &mut File, subtable: u32, run: &mut [RunGlyph], pos: usize) -> io::Result<Option<usize>> {
+    let format = read_u16_at(file, subtable)?;
+    let coverage_offset = subtable + file.read_u16::<BigEndian>()? as u32;
+    let Some(coverage) = otlayout::coverage_index(file, coverage_offset, run[pos].0)? else { return Ok(None) };
+
+    match format {
+        1 => {
+            // Format 1: add deltaGlyphID modulo 65536
+            let delta = read_u16_at(file, subtable + 4)?;
+            run[pos].0 = (run[pos].0 + delta as u32) % 65536;
+        }
+        2 => {
+            // Format 2: substitute array indexed by coverage
+            run[pos].0 = read_u16_at(file, subtable + 6 + coverage as u32 * 2)? as u32;
+        }
+        _ => return Ok(None),
+    }
+    Ok(Some(pos + 1))
+}
+
+fn apply_multiple(file: &mut File, subtable: u32, run: &mut Vec<RunGlyph>, pos: usize) -> io::Result<Option<usize>> {
+    // Format 1: a Sequence of glyphs per covered glyph replaces that glyph
+    let coverage_offset = subtable + read_u16_at(file, subtable + 2)? as u32;
+    let Some(coverage) = otlayout::coverage_index(file, coverage_offset, run[pos].0)? else { return Ok(None) };
+
+    let sequence_offset = subtable + read_u16_at(file, subtable + 6 + coverage as u32 * 2)? as u32;
+    let glyph_count = read_u16_at(file, sequence_offset)?;
+    let substitutes = read_u16_array(file, glyph_count)?;
+
+    let cluster = run[pos].1;
+    run.splice(pos..pos + 1, substitutes.iter().map(|&g| (g as u32, cluster)));
+    Ok(Some(pos + substitutes.len()))
+}
+
+fn apply_alternate(file: &mut File, subtable: u32, run: &mut [RunGlyph], pos: usize) -> io::Result<Option<usize>> {
+    // Format 1: an AlternateSet per covered glyph, the first alternate is used
+    let coverage_offset = subtable + read_u16_at(file, subtable + 2)? as u32;
+    let Some(coverage) = otlayout::coverage_index(file, coverage_offset, run[pos].0)? else { return Ok(None) };
+
+    let alternate_set_offset = subtable + read_u16_at(file, subtable + 6 + coverage as u32 * 2)? as u32;
+    let glyph_count = read_u16_at(file, alternate_set_offset)?;
+    if glyph_count == 0 {
+        return Ok(None);
+    }
+    run[pos].0 = file.read_u16::<BigEndian>()? as u32;
+    Ok(Some(pos + 1))
+}
+
+fn apply_ligature(file: &mut File, subtable: u32, filter: &GlyphFilter, run: &mut Vec<RunGlyph>, pos: usize) -> io::Result<Option<usize>> {
+    // Format 1: a LigatureSet per covered first glyph, ligatures listed in order of preference.
+    // Skipped glyphs between the components stay, behind the ligature.
+    let coverage_offset = subtable + read_u16_at(file, subtable + 2)? as u32;
+    let Some(coverage) = otlayout::coverage_index(file, coverage_offset, run[pos].0)? else { return Ok(None) };
+
+    let ligature_set_offset = subtable + read_u16_at(file, subtable + 6 + coverage as u32 * 2)? as u32;
+    let ligature_count = read_u16_at(file, ligature_set_offset)?;
+    let ligature_offsets = read_u16_array(file, ligature_count)?;
+
+    for ligature_offset in ligature_offsets {
+        let ligature = ligature_set_offset + ligature_offset as u32;
+        let ligature_glyph = read_u16_at(file, ligature)? as u32;
+        let component_count = file.read_u16::<BigEndian>()? as usize;
+        if component_count == 0 {
+            continue;
+        }
+        // The first component is the covered glyph, the others follow it
+        let components = read_u16_array(file, (component_count - 1) as u16)?;
+        let Some(positions) = filter.next(file, &run_glyphs(run), pos, components.len())? else { continue };
+        let matches = components
+            .iter()
+            .zip(&positions)
+            .all(|(&g, &p)| run[p].0 == g as u32);
+        if matches {
+            for &p in positions.iter().rev() {
+                run.remove(p);
+            }
+            run[pos].0 = ligature_glyph;
+            return Ok(Some(pos + 1));
+        }
+    }
+    Ok(None)
+}
+
+fn run_glyphs(run: &[RunGlyph]) -> Vec<u32> {
+    run.iter().map(|&(g, _)| g).collect()
+}
+
+fn match_context(file: &mut File, subtable: u32, filter: &GlyphFilter, run: &[RunGlyph], pos: usize) -> io::Result<Option<(Vec<usize>, Vec<LookupRecord>)>> {
+    //Contextual substitution: an input sequence starting at pos, returns the positions of its glyphs
+    //and the lookups to apply inside it
+    let format = read_u16_at(file, subtable)?;
+    match format {
+        1 | 2 => {
+            let coverage_offset = subtable + file.read_u16::<BigEndian>()? as u32;
+            let class_def_offset = if format == 2 { subtable + file.read_u16::<BigEndian>()? as u32 } else { 0 };
+            let set_count = file.read_u16::<BigEndian>()?;
+            let set_offsets = read_u16_array(file, set_count)?;
+            let Some(coverage) = otlayout::coverage_index(file, coverage_offset, run[pos].0)? else { return Ok(None) };
+
+            // Format 1 rule sets are indexed by coverage, format 2 by the class of the first glyph
+            let set_index = if format == 1 { coverage } else { otlayout::glyph_class(file, class_def_offset, run[pos].0)? };
+            let Some(&set_offset) = set_offsets.get(set_index as usize) else { return Ok(None) };
+            if set_offset == 0 {
+                return Ok(None);
+            }
+            let set = subtable + set_offset as u32;
+            let rule_count = read_u16_at(file, set)?;
+            let rule_offsets = read_u16_array(file, rule_count)?;
+
+            for rule_offset in rule_offsets {
+                let rule = set + rule_offset as u32;
+                let glyph_count = read_u16_at(file, rule)? as usize;
+                let record_count = file.read_u16::<BigEndian>()?;
+                let input = read_u16_array(file, glyph_count.saturating_sub(1) as u16)?;
+                let records = read_lookup_records(file, record_count)?;
+
+                if glyph_count == 0 {
+                    continue;
+                }
+                let Some(positions) = filter.next(file, &run_glyphs(run), pos, input.len())? else { continue };
+                let mut matched = true;
+                for (&value, &p) in input.iter().zip(&positions) {
+                    let glyph = run[p].0;
+                    let actual = if format == 1 { glyph } else { otlayout::glyph_class(file, class_def_offset, glyph)? as u32 };
+                    if actual != value as u32 {
+                        matched = false;
+                        break;
+                    }
+                }
+                if matched {
+                    return Ok(Some(([pos].into_iter().chain(positions).collect(), records)));
+                }
+            }
+            Ok(None)
+        }
+        3 => {
+            // Format 3: one coverage table per input position
+            let glyph_count = file.read_u16::<BigEndian>()? as usize;
+            let record_count = file.read_u16::<BigEndian>()?;
+            let coverage_offsets = read_u16_array(file, glyph_count as u16)?;
+            let records = read_lookup_records(file, record_count)?;
+            if glyph_count == 0 {
+                return Ok(None);
+            }
+            let Some(positions) = filter.next(file, &run_glyphs(run), pos, glyph_count - 1)? else { return Ok(None) };
+            let positions: Vec<usize> = [pos].into_iter().chain(positions).collect();
+            for (&coverage_offset, &p) in coverage_offsets.iter().zip(&positions) {
+                if otlayout::coverage_index(file, subtable + coverage_offset as u32, run[p].0)?.is_none() {
+                    return Ok(None);
+                }
+            }
+            Ok(Some((positions, records)))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn match_chaining_context(file: &mut File, subtable: u32, filter: &GlyphFilter, run: &[RunGlyph], pos: usize) -> io::Result<Option<(Vec<usize>, Vec<LookupRecord>)>> {
+    //Chaining contextual substitution: like match_context with a backtrack sequence before pos
+    //(stored nearest glyph first) and a lookahead sequence after the input
+    let format = read_u16_at(file, subtable)?;
+    match format {
+        1 | 2 => {
+            let coverage_offset = subtable + file.read_u16::<BigEndian>()? as u32;
+            let mut class_defs = [0u32; 3];
+            if format == 2 {
+                for class_def in class_defs.iter_mut() {
+                    *class_def = subtable + file.read_u16::<BigEndian>()? as u32;
+                }
+            }
+            let set_count = file.read_u16::<BigEndian>()?;
+            let set_offsets = read_u16_array(file, set_count)?;
+            let Some(coverage) = otlayout::coverage_index(file, coverage_offset, run[pos].0)? else { return Ok(None) };
+
+            let set_index = if format == 1 { coverage } else { otlayout::glyph_class(file, class_defs[1], run[pos].0)? };
+            let Some(&set_offset) = set_offsets.get(set_index as usize) else { return Ok(None) };
+            if set_offset == 0 {
+                return Ok(None);
+            }
+            let set = subtable + set_offset as u32;
+            let rule_count = read_u16_at(file, set)?;
+            let rule_offsets = read_u16_array(file, rule_count)?;
+
+            for rule_offset in rule_offsets {
+                file.seek(SeekFrom::Start((set + rule_offset as u32) as u64))?;
+                let backtrack_count = file.read_u16::<BigEndian>()?;
+                let backtrack = read_u16_array(file, backtrack_count)?;
+                let input_count = file.read_u16::<BigEndian>()? as usize;
+                let input = read_u16_array(file, input_count.saturating_sub(1) as u16)?;
+                let lookahead_count = file.read_u16::<BigEndian>()?;
+                let lookahead = read_u16_array(file, lookahead_count)?;
+                let record_count = file.read_u16::<BigEndian>()?;
+                let records = read_lookup_records(file, record_count)?;
+
+                if input_count == 0 {
+                    continue;
+                }
+                let Some((backtrack_positions, input_positions, lookahead_positions)) =
+                    chain_positions(file, filter, run, pos, backtrack.len(), input_count, lookahead.len())?
+                else {
+                    continue;
+                };
+
+                // Compare glyph ids (format 1) or the classes from the matching ClassDef (format 2)
+                let mut sequences = Vec::new();
+                for (&value, &p) in backtrack.iter().zip(&backtrack_positions) {
+                    sequences.push((class_defs[0], run[p].0, value));
+                }
+                for (&value, &p) in input.iter().zip(&input_positions[1..]) {
+                    sequences.push((class_defs[1], run[p].0, value));
+                }
+                for (&value, &p) in lookahead.iter().zip(&lookahead_positions) {
+                    sequences.push((class_defs[2], run[p].0, value));
+                }
+                let mut matched = true;
+                for (class_def, glyph, value) in sequences {
+                    let actual = if format == 1 { glyph } else { otlayout::glyph_class(file, class_def, glyph)? as u32 };
+                    if actual != value as u32 {
+                        matched = false;
+                        break;
+                    }
+                }
+                if matched {
+                    return Ok(Some((input_positions, records)));
+                }
+            }
+            Ok(None)
+        }
+        3 => {
+            // Format 3: one coverage table per backtrack, input and lookahead position
+            let backtrack_count = file.read_u16::<BigEndian>()?;
+            let backtrack = read_u16_array(file, backtrack_count)?;
+            let input_count = file.read_u16::<BigEndian>()?;
+            let input = read_u16_array(file, input_count)?;
+            let lookahead_count = file.read_u16::<BigEndian>()?;
+            let lookahead = read_u16_array(file, lookahead_count)?;
+            let record_count = file.read_u16::<BigEndian>()?;
+            let records = read_lookup_records(file, record_count)?;
+
+            if input.is_empty() {
+                return Ok(None);
+            }
+            let Some((backtrack_positions, input_positions, lookahead_positions)) =
+                chain_positions(file, filter, run, pos, backtrack.len(), input.len(), lookahead.len())?
+            else {
+                return Ok(None);
+            };
+            let checks = backtrack
+                .iter()
+                .zip(&backtrack_positions)
+                .chain(input.iter().zip(&input_positions))
+                .chain(lookahead.iter().zip(&lookahead_positions));
+            for (&coverage_offset, &p) in checks {
+                if otlayout::coverage_index(file, subtable + coverage_offset as u32, run[p].0)?.is_none() {
+                    return Ok(None);
+                }
+            }
+            Ok(Some((input_positions, records)))
+        }
+        _ => Ok(None),
+    }
+}
+
+//The positions of the backtrack (nearest first), input (starting at pos) and lookahead glyphs of a chain rule
+type ChainPositions = (Vec<usize>, Vec<usize>, Vec<usize>);
+
+fn chain_positions(file: &mut File, filter: &GlyphFilter, run: &[RunGlyph], pos: usize, backtrack_count: usize, input_count: usize, lookahead_count: usize) -> io::Result<Option<ChainPositions>> {
+    //Finds the glyphs of the rule's sequences around pos, looking past the glyphs the lookup skips
+    let glyphs = run_glyphs(run);
+    let Some(backtrack) = filter.previous(file, &glyphs, pos, backtrack_count)? else { return Ok(None) };
+    let Some(input) = filter.next(file, &glyphs, pos, input_count - 1)? else { return Ok(None) };
+    let last = input.last().copied().unwrap_or(pos);
+    let Some(lookahead) = filter.next(file, &glyphs, last, lookahead_count)? else { return Ok(None) };
+    Ok(Some((backtrack, [pos].into_iter().chain(input).collect(), lookahead)))
+}
+
+fn read_lookup_records(file: &mut File, count: u16) -> io::Result<Vec<LookupRecord>> {
+    let mut records = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let sequence_index = file.read_u16::<BigEndian>()?;
+        let lookup_index = file.read_u16::<BigEndian>()?;
+        records.push((sequence_index, lookup_index));
+    }
+    Ok(records)
+}
+
+fn apply_lookup_records(
+    file: &mut File,
+    gsub_offset: u32,
+    gdef_offset: u32,
+    run: &mut Vec<RunGlyph>,
+    mut positions: Vec<usize>,
+    records: &[LookupRecord],
+    depth: u32,
+) -> io::Result<usize> {
+    //Applies the nested lookups in record order at the matched input glyphs. A nested lookup may change
+    //the number of glyphs, so the positions of the input glyphs after it are shifted along.
+    let mut end = positions.last().map_or(0, |&p| p + 1);
+    if depth >= MAX_NESTING {
+        println!("Warning: GSUB contextual lookups nested too deep");
+        return Ok(end);
+    }
+
+    for &(sequence_index, lookup_index) in records {
+        let Some(&at) = positions.get(sequence_index as usize) else { continue };
+        if at >= run.len() {
+            continue;
+        }
+        let length_before = run.len() as isize;
+        apply_lookup(file, gsub_offset, gdef_offset, lookup_index, run, at, depth + 1)?;
+        let delta = run.len() as isize - length_before;
+        if delta != 0 {
+            for position in positions.iter_mut().skip(sequence_index as usize + 1) {
+                *position = (*position as isize + delta).max(at as isize) as usize;
+            }
+            end = (end as isize + delta).max(at as isize) as usize;
+        }
+    }
+    Ok(end)
+}
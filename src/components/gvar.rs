@@ -0,0 +1,316 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use byteorder::{BigEndian, ReadBytesExt};
+use crate::components::glyf;
+use crate::components::outline::Point;
+use crate::components::variations;
+
+//The glyph variations table stores, per glyph, tuple variations: point deltas that apply
+//with a strength depending on where the instance is in the region of the tuple.
+//Four phantom points follow the outline points, the first two carry the horizontal advance.
+//Outline points a tuple leaves out are inferred from their touched neighbours (IUP).
+
+//Tuple variation count flag: the serialized data starts with point numbers shared by all tuples
+const SHARED_POINT_NUMBERS: u16 = 0x8000;
+//Tuple index flags
+const EMBEDDED_PEAK_TUPLE: u16 = 0x8000;
+const INTERMEDIATE_REGION: u16 = 0x4000;
+const PRIVATE_POINT_NUMBERS: u16 = 0x2000;
+const TUPLE_INDEX_MASK: u16 = 0x0FFF;
+
+pub fn advance_delta(file: &mut File, gvar_offset: u32, glyf_offset: u32, loca_offset: u32, head_offset: u32, glyph_index: u32, coords: &[f32]) -> io::Result<f32> {
+    //The advance changes by how far the second phantom point moves away from the first
+    let num_points = glyf::get_point_count(file, glyf_offset, loca_offset, head_offset, glyph_index)?;
+    let deltas = glyph_deltas(file, gvar_offset, glyph_index, num_points + 4, &[], coords)?;
+    Ok(deltas[num_points + 1].0 - deltas[num_points].0)
+}
+
+pub fn glyph_deltas(file: &mut File, gvar_offset: u32, glyph_index: u32, num_points: usize, contours: &[Vec<Point>], coords: &[f32]) -> io::Result<Vec<(f32, f32)>> {
+    //Sums the scaled deltas of every tuple variation of the glyph for the instance.
+    //Returns one (x, y) delta per point, phantom points included when counted in num_points.
+    //The contours of a simple glyph are needed to infer the points a tuple does not list,
+    //composite glyphs (one point per component) pass none and those points get no delta.
+    let mut deltas = vec![(0.0f32, 0.0f32); num_points];
+
+    // Header: majorVersion, minorVersion, axisCount, sharedTupleCount, sharedTuplesOffset (32),
+    // glyphCount, flags, glyphVariationDataArrayOffset (32), then glyphCount + 1 offsets
+    file.seek(SeekFrom::Start(gvar_offset as u64 + 4))?;
+    let axis_count = file.read_u16::<BigEndian>()? as usize;
+    let _shared_tuple_count = file.read_u16::<BigEndian>()?;
+    let shared_tuples = gvar_offset + file.read_u32::<BigEndian>()?;
+    let glyph_count = file.read_u16::<BigEndian>()?;
+    let flags = file.read_u16::<BigEndian>()?;
+    let data_array = gvar_offset + file.read_u32::<BigEndian>()?;
+    if glyph_index >= glyph_count as u32 {
+        return Ok(deltas);
+    }
+
+    // Offsets are 32 bit when flag bit 0 is set, otherwise 16 bit and halved
+    let (start, end) = if flags & 1 != 0 {
+        file.seek(SeekFrom::Current(glyph_index as i64 * 4))?;
+        (file.read_u32::<BigEndian>()?, file.read_u32::<BigEndian>()?)
+    } else {
+        file.seek(SeekFrom::Current(glyph_index as i64 * 2))?;
+        (file.read_u16::<BigEndian>()? as u32 * 2, file.read_u16::<BigEndian>()? as u32 * 2)
+    };
+    if end <= start {
+        return Ok(deltas);
+    }
+    let glyph_data = data_array + start;
+
+    // GlyphVariationData: tupleVariationCount, dataOffset, tupleVariationHeaders[]
+    file.seek(SeekFrom::Start(glyph_data as u64))?;
+    let tuple_variation_count = file.read_u16::<BigEndian>()?;
+    let data_offset = file.read_u16::<BigEndian>()?;
+    let mut header_position = glyph_data as u64 + 4;
+    let mut data_position = glyph_data as u64 + data_offset as u64;
+
+    let mut shared_points = None;
+    if tuple_variation_count & SHARED_POINT_NUMBERS != 0 {
+        file.seek(SeekFrom::Start(data_position))?;
+        shared_points = read_packed_points(file)?;
+        data_position = file.stream_position()?;
+    }
+
+    for _ in 0..(tuple_variation_count & 0x0FFF) {
+        // TupleVariationHeader: variationDataSize, tupleIndex, then optional peak and intermediate tuples
+        file.seek(SeekFrom::Start(header_position))?;
+        let variation_data_size = file.read_u16::<BigEndian>()?;
+        let tuple_index = file.read_u16::<BigEndian>()?;
+
+        let peak = if tuple_index & EMBEDDED_PEAK_TUPLE != 0 {
+            read_tuple(file, axis_count)?
+        } else {
+            let position = file.stream_position()?;
+            let shared = shared_tuples as u64 + (tuple_index & TUPLE_INDEX_MASK) as u64 * axis_count as u64 * 2;
+            file.seek(SeekFrom::Start(shared))?;
+            let tuple = read_tuple(file, axis_count)?;
+            file.seek(SeekFrom::Start(position))?;
+            tuple
+        };
+        let intermediate = if tuple_index & INTERMEDIATE_REGION != 0 {
+            Some((read_tuple(file, axis_count)?, read_tuple(file, axis_count)?))
+        } else {
+            None
+        };
+        header_position = file.stream_position()?;
+
+        let scalar = tuple_scalar(coords, &peak, intermediate.as_ref());
+        if scalar != 0.0 {
+            file.seek(SeekFrom::Start(data_position))?;
+            let points = if tuple_index & PRIVATE_POINT_NUMBERS != 0 {
+                read_packed_points(file)?
+            } else {
+                shared_points.clone()
+            };
+            // None means every point of the glyph has a delta
+            let points = points.unwrap_or_else(|| (0..num_points as u16).collect());
+            let x_deltas = read_packed_deltas(file, points.len())?;
+            let y_deltas = read_packed_deltas(file, points.len())?;
+
+            let mut tuple_deltas: Vec<Option<(f32, f32)>> = vec![None; num_points];
+            for ((&point, &dx), &dy) in points.iter().zip(&x_deltas).zip(&y_deltas) {
+                if let Some(delta) = tuple_deltas.get_mut(point as usize) {
+                    *delta = Some((dx as f32, dy as f32));
+                }
+            }
+            infer_untouched(contours, &mut tuple_deltas);
+            for (delta, tuple_delta) in deltas.iter_mut().zip(&tuple_deltas) {
+                if let Some((dx, dy)) = tuple_delta {
+                    delta.0 += dx * scalar;
+                    delta.1 += dy * scalar;
+                }
+            }
+        }
+
+        data_position += variation_data_size as u64;
+    }
+
+    Ok(deltas)
+}
+
+fn infer_untouched(contours: &[Vec<Point>], deltas: &mut [Option<(f32, f32)>]) {
+    //Interpolation of untouched points, per contour and per direction: a point between the
+    //coordinates of its previous and next touched point gets the interpolated delta, a point
+    //outside of them the delta of the nearer one. Contours without touched points don't move.
+    let mut first = 0;
+    for contour in contours {
+        let end = first + contour.len();
+        if end > deltas.len() {
+            break;
+        }
+        let touched: Vec<usize> = (first..end).filter(|&i| deltas[i].is_some()).collect();
+        if !touched.is_empty() && touched.len() < contour.len() {
+            for i in first..end {
+                if deltas[i].is_some() {
+                    continue;
+                }
+                // The touched points before and after, wrapping around the contour
+                let prev = touched.iter().rev().find(|&&t| t < i).or(touched.last()).copied().unwrap_or(i);
+                let next = touched.iter().find(|&&t| t > i).or(touched.first()).copied().unwrap_or(i);
+                let (p, q, r) = (&contour[i - first], &contour[prev - first], &contour[next - first]);
+                let (dq, dr) = (deltas[prev].unwrap_or_default(), deltas[next].unwrap_or_default());
+                deltas[i] = Some((
+                    interpolate(p.x, q.x, r.x, dq.0, dr.0),
+                    interpolate(p.y, q.y, r.y, dq.1, dr.1),
+                ));
+            }
+        }
+        first = end;
+    }
+}
+
+fn interpolate(coord: f32, coord1: f32, coord2: f32, delta1: f32, delta2: f32) -> f32 {
+    if coord1 == coord2 {
+        return if delta1 == delta2 { delta1 } else { 0.0 };
+    }
+    let (low, high, delta_low, delta_high) = if coord1 < coord2 {
+        (coord1, coord2, delta1, delta2)
+    } else {
+        (coord2, coord1, delta2, delta1)
+    };
+    if coord <= low {
+        delta_low
+    } else if coord >= high {
+        delta_high
+    } else {
+        delta_low + (coord - low) / (high - low) * (delta_high - delta_low)
+    }
+}
+
+fn read_tuple(file: &mut File, axis_count: usize) -> io::Result<Vec<f32>> {
+    (0..axis_count).map(|_| variations::read_f2dot14(file)).collect()
+}
+
+fn tuple_scalar(coords: &[f32], peak: &[f32], intermediate: Option<&(Vec<f32>, Vec<f32>)>) -> f32 {
+    //Without an intermediate region a tuple reaches from 0 to its peak on every axis
+    let mut scalar = 1.0;
+    for (axis, &peak) in peak.iter().enumerate() {
+        let coord = coords.get(axis).copied().unwrap_or(0.0);
+        let (start, end) = match intermediate {
+            Some((start, end)) => (start[axis], end[axis]),
+            None => (peak.min(0.0), peak.max(0.0)),
+        };
+        scalar *= variations::axis_scalar(coord, start, peak, end);
+        if scalar == 0.0 {
+            break;
+        }
+    }
+    scalar
+}
+
+fn read_packed_points<R: Read>(file: &mut R) -> io::Result<Option<Vec<u16>>> {
+    //A count (one byte, or two with the high bit set) followed by runs of point number differences,
+    //a count of zero means all points
+    let first = file.read_u8()? as u16;
+    let count = if first & 0x80 != 0 {
+        ((first & 0x7F) << 8) | file.read_u8()? as u16
+    } else {
+        first
+    };
+    if count == 0 {
+        return Ok(None);
+    }
+
+    // Run control byte: 0x80 points are 16 bit, low 7 bits are the run length - 1
+    let mut points = Vec::with_capacity(count as usize);
+    let mut point: u16 = 0;
+    while points.len() < count as usize {
+        let control = file.read_u8()?;
+        let run_length = (control & 0x7F) as usize + 1;
+        for _ in 0..run_length {
+            let difference = if control & 0x80 != 0 {
+                file.read_u16::<BigEndian>()?
+            } else {
+                file.read_u8()? as u16
+            };
+            point = point.wrapping_add(difference);
+            points.push(point);
+        }
+    }
+    points.truncate(count as usize);
+    Ok(Some(points))
+}
+
+fn read_packed_deltas<R: Read>(file: &mut R, count: usize) -> io::Result<Vec<i32>> {
+    //Runs with a control byte: 0x80 deltas are zero (not stored), 0x40 deltas are 16 bit,
+    //both 32 bit, otherwise 8 bit. The low 6 bits are the run length - 1.
+    let mut deltas = Vec::with_capacity(count);
+    while deltas.len() < count {
+        let control = file.read_u8()?;
+        let run_length = (control & 0x3F) as usize + 1;
+        for _ in 0..run_length {
+            let delta = match control & 0xC0 {
+                0x80 => 0,
+                0x40 => file.read_i16::<BigEndian>()? as i32,
+                0xC0 => file.read_i32::<BigEndian>()?,
+                _ => file.read_i8()? as i32,
+            };
+            deltas.push(delta);
+        }
+    }
+    deltas.truncate(count);
+    Ok(deltas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f32, y: f32) -> Point {
+        Point { x, y, on_curve: true }
+    }
+
+    #[test]
+    fn packed_points_zero_count_means_all_points() {
+        assert_eq!(read_packed_points(&mut &[0x00u8][..]).unwrap(), None);
+    }
+
+    #[test]
+    fn packed_points_add_up_differences() {
+        // 3 points in one run of 8 bit differences
+        let data = [0x03, 0x02, 1, 2, 3];
+        assert_eq!(read_packed_points(&mut &data[..]).unwrap(), Some(vec![1, 3, 6]));
+    }
+
+    #[test]
+    fn packed_points_two_byte_count_and_16_bit_runs() {
+        // Count 3 in two bytes, a run of one 16 bit difference, then a run of two 8 bit ones
+        let data = [0x80, 0x03, 0x80, 0x01, 0x00, 0x01, 1, 2];
+        assert_eq!(read_packed_points(&mut &data[..]).unwrap(), Some(vec![256, 257, 259]));
+    }
+
+    #[test]
+    fn packed_deltas_all_run_types() {
+        let data = [
+            0x01, 0xFF, 0x05, // two 8 bit deltas
+            0x81, // two zero deltas
+            0x40, 0xFE, 0x0C, // one 16 bit delta
+            0xC0, 0x00, 0x01, 0x00, 0x00, // one 32 bit delta
+        ];
+        assert_eq!(read_packed_deltas(&mut &data[..], 6).unwrap(), vec![-1, 5, 0, 0, -500, 65536]);
+    }
+
+    #[test]
+    fn untouched_points_interpolate_and_wrap_around() {
+        let contours = vec![
+            // Touched in the middle, the untouched ends find their neighbours around the contour
+            vec![point(0.0, 0.0), point(50.0, 0.0), point(100.0, 100.0), point(150.0, 100.0)],
+            // An untouched point between two touched ones
+            vec![point(0.0, 0.0), point(25.0, 0.0), point(100.0, 0.0)],
+            // No touched points, nothing moves
+            vec![point(0.0, 0.0), point(10.0, 10.0)],
+        ];
+        let mut deltas = vec![
+            None, Some((10.0, 0.0)), Some((20.0, 5.0)), None,
+            Some((0.0, 0.0)), None, Some((8.0, 0.0)),
+            None, None,
+        ];
+        infer_untouched(&contours, &mut deltas);
+        assert_eq!(deltas, vec![
+            Some((10.0, 0.0)), Some((10.0, 0.0)), Some((20.0, 5.0)), Some((20.0, 5.0)),
+            Some((0.0, 0.0)), Some((2.0, 0.0)), Some((8.0, 0.0)),
+            None, None,
+        ]);
+    }
+}
@@ -7,9 +7,8 @@ pub fn parse_hmtx(
     glyph_indices: &[u32],
     num_h_metrics: u16,
     hmtx_offset: u32,
-    _hhea_offset: u32,
     maxp_offset: u32,
-) -> io::Result<u32> {
+) -> io::Result<Vec<u16>> {
    
    //Glyph metrics used for horizontal text layout include glyph advance widths, side bearings and X-direction min and max values (xMin, xMax). 
    //These are derived using a combination of the glyph outline data ('glyf', 'CFF ' or CFF2) and the horizontal metrics table. 
@@ -40,9 +39,8 @@ pub fn parse_hmtx(
         lsbs.push(lsb);
     }
 
-    let mut total_width = 0;
-    
     // Map glyph indices to advance widths
+    let mut advance_widths = Vec::new();
     for &glyph_index in glyph_indices {
         let advance_width = if (glyph_index as u16) < num_h_metrics {
             h_metrics[glyph_index as usize]
@@ -56,8 +54,8 @@ pub fn parse_hmtx(
             glyph_index, advance_width
         );
 
-        total_width += advance_width as u32;    
+        advance_widths.push(advance_width);
     }
 
-    Ok(total_width)
+    Ok(advance_widths)
 }
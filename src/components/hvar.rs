@@ -0,0 +1,23 @@
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom};
+use byteorder::{BigEndian, ReadBytesExt};
+use crate::components::variations;
+
+//The horizontal metrics variations table holds the advance width deltas of a variable font,
+//so they don't have to be derived from the gvar phantom points.
+
+pub fn advance_delta(file: &mut File, hvar_offset: u32, glyph_index: u32, coords: &[f32]) -> io::Result<f32> {
+    // Header: majorVersion, minorVersion, itemVariationStoreOffset (32), advanceWidthMappingOffset (32),
+    // lsbMappingOffset (32), rsbMappingOffset (32)
+    file.seek(SeekFrom::Start(hvar_offset as u64 + 4))?;
+    let store_offset = file.read_u32::<BigEndian>()?;
+    let advance_mapping_offset = file.read_u32::<BigEndian>()?;
+
+    // Without a mapping the glyph id is the inner index of the first delta set list
+    let (outer, inner) = if advance_mapping_offset != 0 {
+        variations::delta_set_index(file, hvar_offset + advance_mapping_offset, glyph_index)?
+    } else {
+        (0, glyph_index as u16)
+    };
+    variations::item_delta(file, hvar_offset + store_offset, outer, inner, coords)
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+//Hyphenation with Liang's patterns, as TeX does it. The patterns come from the hyph-*.pat.txt files of
+//the hyph-utf8 project, e.g. hyph-de-1996.pat.txt or hyph-en-gb.pat.txt, one pattern per line.
+//A pattern like "he2n" says how much a break between its letters is wanted, "." marks the start or end of a word.
+//Every pattern matching somewhere in a word votes for the breaks it covers, odd values allow a hyphen.
+
+//Letters that always stay together at the start and the end of a word (\lefthyphenmin and \righthyphenmin)
+const LEFT_MIN: usize = 2;
+const RIGHT_MIN: usize = 2;
+
+pub struct Patterns {
+    //The letters of every pattern and the values of the gaps before, between and after them
+    values: HashMap<String, Vec<u8>>,
+    //Letters in the longest pattern, no longer part of a word can match
+    max_length: usize,
+}
+
+pub fn load_patterns(path: &str) -> io::Result<Patterns> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("Can't read hyphenation patterns '{}': {}", path, e)))?;
+    let patterns = parse_patterns(&content);
+    if patterns.values.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("No hyphenation patterns in '{}'", path)));
+    }
+    Ok(patterns)
+}
+
+fn parse_patterns(content: &str) -> Patterns {
+    let mut values = HashMap::new();
+    let mut max_length = 0;
+    // Patterns are separated by whitespace, "%" starts a comment like in the TeX sources
+    for pattern in content.lines().flat_map(|line| line.split('%').next().unwrap_or("").split_whitespace()) {
+        let mut letters = String::new();
+        let mut gaps = vec![0u8];
+        for c in pattern.chars() {
+            match c.to_digit(10) {
+                Some(digit) => *gaps.last_mut().unwrap() = digit as u8,
+                None => {
+                    letters.extend(c.to_lowercase());
+                    gaps.push(0);
+                }
+            }
+        }
+        max_length = max_length.max(letters.chars().count());
+        values.insert(letters, gaps);
+    }
+    Patterns { values, max_length }
+}
+
+impl Patterns {
+    pub fn hyphenation_points(&self, chars: &[char]) -> Vec<usize> {
+        //Where a hyphen may go in the characters, as the index of the character after it.
+        //Every run of letters is hyphenated as a word, so "door," and "(door)" break like "door".
+        let mut points = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            if !chars[i].is_alphabetic() {
+                i += 1;
+                continue;
+            }
+            let word_start = i;
+            while i < chars.len() && chars[i].is_alphabetic() {
+                i += 1;
+            }
+            points.extend(self.hyphenate_word(&chars[word_start..i]).into_iter().map(|p| word_start + p));
+        }
+        points
+    }
+
+    fn hyphenate_word(&self, word: &[char]) -> Vec<usize> {
+        if word.len() < LEFT_MIN + RIGHT_MIN {
+            return Vec::new();
+        }
+        // One lowercase letter per character, so the indices stay those of the word
+        let mut dotted = vec!['.'];
+        dotted.extend(word.iter().map(|c| c.to_lowercase().next().unwrap_or(*c)));
+        dotted.push('.');
+
+        // gaps[i] is the value of the gap before dotted[i], the highest vote of all matching patterns
+        let mut gaps = vec![0u8; dotted.len() + 1];
+        for start in 0..dotted.len() {
+            for end in start + 1..=dotted.len().min(start + self.max_length) {
+                let letters: String = dotted[start..end].iter().collect();
+                if let Some(values) = self.values.get(&letters) {
+                    for (k, &value) in values.iter().enumerate() {
+                        gaps[start + k] = gaps[start + k].max(value);
+                    }
+                }
+            }
+        }
+        // The gap before letter i of the word is the one before dotted[i + 1]
+        (LEFT_MIN..=word.len() - RIGHT_MIN).filter(|&i| gaps[i + 1] % 2 == 1).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //The patterns Liang's thesis hyphenates "hyphenation" with
+    const LIANG: &str = "hy3ph he2n hena4 hen5at 1na n2at 1tio 2io o2n";
+
+    fn chars(text: &str) -> Vec<char> {
+        text.chars().collect()
+    }
+
+    #[test]
+    fn parse_splits_letters_and_gap_values() {
+        let patterns = parse_patterns("% comment\n.ach4 hen5at\n1na % trailing comment");
+        assert_eq!(patterns.values.len(), 3);
+        assert_eq!(patterns.values[".ach"], vec![0, 0, 0, 0, 4]);
+        assert_eq!(patterns.values["henat"], vec![0, 0, 0, 5, 0, 0]);
+        assert_eq!(patterns.values["na"], vec![1, 0, 0]);
+        assert_eq!(patterns.max_length, 5);
+    }
+
+    #[test]
+    fn hyphenates_liangs_example() {
+        let patterns = parse_patterns(LIANG);
+        // hy-phen-ation
+        assert_eq!(patterns.hyphenation_points(&chars("hyphenation")), vec![2, 6]);
+        assert_eq!(patterns.hyphenation_points(&chars("Hyphenation")), vec![2, 6]);
+    }
+
+    #[test]
+    fn hyphenates_every_word_on_its_own() {
+        let patterns = parse_patterns(LIANG);
+        assert_eq!(patterns.hyphenation_points(&chars("(hyphenation) hyphenation")), vec![3, 7, 16, 20]);
+    }
+
+    #[test]
+    fn keeps_the_minimum_letters_at_the_ends() {
+        // Odd values before the second letter and after the second to last are ignored
+        let patterns = parse_patterns("1b 1e");
+        assert_eq!(patterns.hyphenation_points(&chars("abcdef")), vec![4]);
+        assert!(patterns.hyphenation_points(&chars("abe")).is_empty());
+    }
+}
@@ -0,0 +1,244 @@
+use std::fs::File;
+use std::io;
+use std::ops::Range;
+use crate::components::features::FeatureSelection;
+use crate::components::gsub::{self, RunGlyph};
+use crate::components::otlayout;
+
+//Shaping of the Indic scripts after the OpenType script development specs.
+//The text is split into syllables, pre-base matras are moved in front of the consonants they follow in the text,
+//the conjunct features run on the part of each syllable they belong to (half forms before the base consonant,
+//below-base and post-base forms after it), and the reph is moved behind the base before the presentation forms.
+
+pub struct IndicScript {
+    //First code point of the script's Unicode block, the blocks share one layout
+    block: u32,
+    //OpenType script tags of the new (version 2) and the old shaping model
+    tag: &'static str,
+    old_tag: &'static str,
+    //Matras drawn before the consonants they follow in the text, as offsets in the block
+    pre_base_matras: &'static [u32],
+}
+
+static SCRIPTS: [IndicScript; 9] = [
+    IndicScript { block: 0x0900, tag: "dev2", old_tag: "deva", pre_base_matras: &[0x3F, 0x4E] },
+    IndicScript { block: 0x0980, tag: "bng2", old_tag: "beng", pre_base_matras: &[0x3F, 0x47, 0x48] },
+    IndicScript { block: 0x0A00, tag: "gur2", old_tag: "guru", pre_base_matras: &[0x3F] },
+    IndicScript { block: 0x0A80, tag: "gjr2", old_tag: "gujr", pre_base_matras: &[0x3F] },
+    IndicScript { block: 0x0B00, tag: "ory2", old_tag: "orya", pre_base_matras: &[0x47] },
+    IndicScript { block: 0x0B80, tag: "tml2", old_tag: "taml", pre_base_matras: &[0x46, 0x47, 0x48] },
+    IndicScript { block: 0x0C00, tag: "tel2", old_tag: "telu", pre_base_matras: &[] },
+    IndicScript { block: 0x0C80, tag: "knd2", old_tag: "knda", pre_base_matras: &[] },
+    IndicScript { block: 0x0D00, tag: "mlm2", old_tag: "mlym", pre_base_matras: &[0x46, 0x47, 0x48] },
+];
+
+//Offset of the letter Ra in every block, Ra and a virama at the start of a syllable become the reph
+const RA: u32 = 0x30;
+
+//Features forming the conjuncts, in the order they are applied
+const BASIC_FEATURES: [&str; 11] = ["nukt", "akhn", "rphf", "rkrf", "pref", "blwf", "abvf", "half", "pstf", "vatu", "cjct"];
+//Features choosing the final glyphs, applied after the reph has moved
+const PRESENTATION_FEATURES: [&str; 5] = ["pres", "abvs", "blws", "psts", "haln"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Category {
+    Consonant,
+    Vowel,
+    Matra,
+    Nukta,
+    Virama,
+    //Candrabindu, anusvara, visarga and the stress marks, they end a syllable
+    Modifier,
+    Joiner,
+    NonJoiner,
+    Other,
+}
+
+impl IndicScript {
+    fn category(&self, c: char) -> Category {
+        match c as u32 {
+            0x200D => return Category::Joiner,
+            0x200C => return Category::NonJoiner,
+            code if !(self.block..self.block + 0x80).contains(&code) => return Category::Other,
+            _ => {}
+        }
+        match c as u32 - self.block {
+            0x01..=0x03 | 0x51..=0x54 => Category::Modifier,
+            0x04..=0x14 | 0x60..=0x61 | 0x72..=0x77 => Category::Vowel,
+            0x15..=0x39 | 0x58..=0x5F | 0x78..=0x7F => Category::Consonant,
+            0x3A..=0x3B | 0x3E..=0x4C | 0x4E..=0x4F | 0x55..=0x57 | 0x62..=0x63 => Category::Matra,
+            0x3C => Category::Nukta,
+            0x4D => Category::Virama,
+            _ => Category::Other,
+        }
+    }
+}
+
+pub fn indic_script(text: &str) -> Option<&'static IndicScript> {
+    //The script of the first character in one of the Indic blocks
+    text.chars()
+        .find_map(|c| SCRIPTS.iter().find(|script| (script.block..script.block + 0x80).contains(&(c as u32))))
+}
+
+pub fn script_tag(file: &mut File, gsub_offset: u32, script: &IndicScript) -> io::Result<&'static str> {
+    //Fonts made for the new shaping model have the version 2 tag, older fonts only the old one
+    if otlayout::has_script(file, gsub_offset, script.tag)? {
+        Ok(script.tag)
+    } else {
+        Ok(script.old_tag)
+    }
+}
+
+fn syllables(categories: &[Category]) -> Vec<Range<usize>> {
+    //Consonant syllables: (C N? H ZWJ|ZWNJ?)* C N? (H ZWJ|ZWNJ?)? followed by matras and modifiers,
+    //vowel syllables: V N? followed by matras and modifiers, anything else stands alone
+    let mut syllables = Vec::new();
+    let mut start = 0;
+    while start < categories.len() {
+        let category_at = |i: usize| categories.get(i).copied().unwrap_or(Category::Other);
+        let mut end = start + 1;
+        if matches!(category_at(start), Category::Consonant | Category::Vowel) {
+            if category_at(end) == Category::Nukta {
+                end += 1;
+            }
+            while category_at(start) == Category::Consonant && category_at(end) == Category::Virama {
+                let mut next = end + 1;
+                if matches!(category_at(next), Category::Joiner | Category::NonJoiner) {
+                    next += 1;
+                }
+                if category_at(next) != Category::Consonant {
+                    end = next;
+                    break;
+                }
+                end = next + 1;
+                if category_at(end) == Category::Nukta {
+                    end += 1;
+                }
+            }
+            while matches!(category_at(end), Category::Matra | Category::Nukta | Category::Virama | Category::Modifier) {
+                end += 1;
+            }
+        }
+        syllables.push(start..end);
+        start = end;
+    }
+    syllables
+}
+
+fn find_base(file: &mut File, gsub_offset: u32, gdef_offset: u32, selection: &FeatureSelection, glyph_indices: &[u32], categories: &[Category], consonants: Range<usize>) -> io::Result<usize> {
+    //The base is the last consonant, unless the font has a below-base or post-base form for it after its virama.
+    //Like Ra in Devanagari KA VIRAMA RA, which is drawn as a stroke below KA.
+    for i in consonants.clone().skip(1).rev() {
+        if categories[i] != Category::Consonant {
+            continue;
+        }
+        let virama = if categories[i - 1] == Category::Virama { i - 1 } else { i - 2 };
+        let mut below_or_post = false;
+        for feature in ["blwf", "pstf"] {
+            for pair in [[glyph_indices[virama], glyph_indices[i]], [glyph_indices[i], glyph_indices[virama]]] {
+                below_or_post |= selection.is_enabled(feature, true) && gsub::would_substitute(file, gsub_offset, gdef_offset, selection, feature, &pair)?;
+            }
+        }
+        if !below_or_post {
+            return Ok(i);
+        }
+    }
+    Ok(consonants.start)
+}
+
+pub fn shape_indic(file: &mut File, gsub_offset: u32, gdef_offset: u32, glyph_indices: &[u32], text: &str, script: &IndicScript, selection: &FeatureSelection) -> io::Result<(Vec<u32>, Vec<usize>)> {
+    //Shapes a run of one Indic script with the run's cmap glyphs, one per character.
+    //Returns the new glyph run and for every glyph the first character of its syllable.
+    let chars: Vec<char> = text.chars().collect();
+    let categories: Vec<Category> = chars.iter().map(|&c| script.category(c)).collect();
+    let mut run: Vec<RunGlyph> = glyph_indices.iter().enumerate().map(|(i, &g)| (g, i)).collect();
+
+    //Which characters the masked features may start at, indexed like the characters
+    let mut reph_mask = vec![false; chars.len()];
+    let mut pre_base_mask = vec![false; chars.len()];
+    let mut post_base_mask = vec![false; chars.len()];
+    let mut reph_syllables = Vec::new();
+
+    let syllables = syllables(&categories);
+    for syllable in &syllables {
+        let start = syllable.start;
+        if categories[start] != Category::Consonant {
+            continue;
+        }
+        let has_reph = syllable.len() >= 3
+            && chars[start] as u32 == script.block + RA
+            && categories[start + 1] == Category::Virama
+            && categories[start + 2] == Category::Consonant;
+        let consonants_start = if has_reph { start + 2 } else { start };
+        let consonants_end = (consonants_start..syllable.end)
+            .find(|&i| matches!(categories[i], Category::Matra | Category::Modifier))
+            .unwrap_or(syllable.end);
+        let base = find_base(file, gsub_offset, gdef_offset, selection, glyph_indices, &categories, consonants_start..consonants_end)?;
+        println!("Debug: Indic syllable {:?} base: {}, reph: {}", &chars[syllable.clone()], chars[base], has_reph);
+
+        if has_reph {
+            reph_mask[start] = true;
+            reph_mask[start + 1] = true;
+            reph_syllables.push(syllable.clone());
+        }
+        pre_base_mask[consonants_start..base].fill(true);
+        post_base_mask[base + 1..syllable.end].fill(true);
+
+        // Pre-base matras go in front of the consonants, the glyphs are still one per character here
+        let pre_base_matras = (base + 1..syllable.end)
+            .filter(|&i| categories[i] == Category::Matra && script.pre_base_matras.contains(&(chars[i] as u32 - script.block)));
+        for (k, i) in pre_base_matras.enumerate() {
+            let matra = run.remove(i);
+            run.insert(consonants_start + k, matra);
+        }
+    }
+
+    let feature_tags = gsub::resolve_features(selection);
+    let (early, late): (Vec<String>, Vec<String>) = feature_tags
+        .into_iter()
+        .filter(|tag| !BASIC_FEATURES.contains(&tag.as_str()) && !PRESENTATION_FEATURES.contains(&tag.as_str()))
+        .partition(|tag| tag == "ccmp" || tag == "locl");
+    gsub::apply_features(file, gsub_offset, gdef_offset, selection, &early, &mut run, None)?;
+    for feature in BASIC_FEATURES {
+        if !selection.is_enabled(feature, true) {
+            continue;
+        }
+        let mask = match feature {
+            "rphf" => Some(reph_mask.as_slice()),
+            "half" => Some(pre_base_mask.as_slice()),
+            "pref" | "blwf" | "abvf" | "pstf" => Some(post_base_mask.as_slice()),
+            _ => None,
+        };
+        gsub::apply_features(file, gsub_offset, gdef_offset, selection, &[feature.to_string()], &mut run, mask)?;
+    }
+
+    // A reph formed when Ra and its virama became one glyph, it moves behind the syllable before the modifiers
+    for syllable in &reph_syllables {
+        let reph_formed = !run.iter().any(|&(_, c)| c == syllable.start + 1);
+        let Some(reph) = run.iter().position(|&(_, c)| c == syllable.start) else { continue };
+        if !reph_formed {
+            continue;
+        }
+        let glyph = run.remove(reph);
+        let last = run
+            .iter()
+            .rposition(|&(_, c)| syllable.contains(&c) && categories[c] != Category::Modifier)
+            .map_or(reph, |p| p + 1);
+        run.insert(last.max(reph), glyph);
+    }
+
+    for feature in PRESENTATION_FEATURES {
+        if selection.is_enabled(feature, true) {
+            gsub::apply_features(file, gsub_offset, gdef_offset, selection, &[feature.to_string()], &mut run, None)?;
+        }
+    }
+    gsub::apply_features(file, gsub_offset, gdef_offset, selection, &late, &mut run, None)?;
+
+    // The glyphs of a syllable are reordered and merged, so the syllable is measured as one cluster
+    for glyph in run.iter_mut() {
+        if let Some(syllable) = syllables.iter().find(|syllable| syllable.contains(&glyph.1)) {
+            glyph.1 = syllable.start;
+        }
+    }
+    Ok(gsub::split_run(glyph_indices, &run))
+}
@@ -0,0 +1,58 @@
+use std::fs::File;
+use std::io;
+use crate::components::{fvar, name};
+
+//A short report about the font: its names and, for variable fonts, the axes and named instances.
+
+//Name ids used in the report
+const FAMILY_NAME: u16 = 1;
+const SUBFAMILY_NAME: u16 = 2;
+const FULL_NAME: u16 = 4;
+const VERSION_STRING: u16 = 5;
+const TYPOGRAPHIC_FAMILY_NAME: u16 = 16;
+const TYPOGRAPHIC_SUBFAMILY_NAME: u16 = 17;
+
+pub fn print_font_info(file: &mut File, name_offset: u32, fvar_offset: u32) -> io::Result<()> {
+    // Typographic names are the ones to use when the font has them
+    let family = match name::get_name(file, name_offset, TYPOGRAPHIC_FAMILY_NAME)? {
+        Some(family) => Some(family),
+        None => name::get_name(file, name_offset, FAMILY_NAME)?,
+    };
+    let subfamily = match name::get_name(file, name_offset, TYPOGRAPHIC_SUBFAMILY_NAME)? {
+        Some(subfamily) => Some(subfamily),
+        None => name::get_name(file, name_offset, SUBFAMILY_NAME)?,
+    };
+    println!("Family:    {}", family.unwrap_or_default());
+    println!("Subfamily: {}", subfamily.unwrap_or_default());
+    println!("Full name: {}", name::get_name(file, name_offset, FULL_NAME)?.unwrap_or_default());
+    println!("Version:   {}", name::get_name(file, name_offset, VERSION_STRING)?.unwrap_or_default());
+
+    if fvar_offset == 0 {
+        println!("Not a variable font");
+        return Ok(());
+    }
+
+    let axes = fvar::parse_fvar(file, fvar_offset)?;
+    println!("Axes:");
+    for axis in &axes {
+        let axis_name = name::get_name(file, name_offset, axis.name_id)?.unwrap_or_default();
+        println!(
+            "  {} {:<12} min {:>8} default {:>8} max {:>8}",
+            axis.tag, axis_name, axis.min_value, axis.default_value, axis.max_value
+        );
+    }
+
+    let instances = fvar::parse_named_instances(file, fvar_offset)?;
+    println!("Named instances:");
+    for instance in &instances {
+        let instance_name = name::get_name(file, name_offset, instance.subfamily_name_id)?.unwrap_or_default();
+        let settings: Vec<String> = axes
+            .iter()
+            .zip(&instance.coordinates)
+            .map(|(axis, value)| format!("{}={}", axis.tag, value))
+            .collect();
+        println!("  {:<24} --axis {}", instance_name, settings.join(","));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use unicode_joining_type::{get_joining_type, JoiningType};
+
+//Joining analysis for Arabic, Syriac and other cursive scripts, from the Unicode ArabicShaping data.
+//Every letter takes an isolated, initial, medial or final form depending on whether it joins
+//to the letter before and after it. Transparent characters (marks) are skipped over.
+
+//GSUB features of the joining forms, in the order they are applied
+pub const FORM_FEATURES: [&str; 4] = ["isol", "fina", "medi", "init"];
+
+pub fn joining_forms(text: &str) -> Vec<Option<&'static str>> {
+    //Returns the form feature for every character in logical order, None for characters that don't join
+    let types: Vec<JoiningType> = text.chars().map(get_joining_type).collect();
+    let mut forms = vec![None; types.len()];
+
+    for (i, &joining_type) in types.iter().enumerate() {
+        if !matches!(joining_type, JoiningType::DualJoining | JoiningType::RightJoining | JoiningType::LeftJoining) {
+            continue;
+        }
+        let previous = types[..i].iter().rev().find(|&&t| t != JoiningType::Transparent);
+        let next = types[i + 1..].iter().find(|&&t| t != JoiningType::Transparent);
+
+        // A letter joins backwards when it and the letter before can join on that side, and the same forwards
+        let joins_previous = joins_backwards(joining_type) && previous.is_some_and(|&t| joins_forwards(t));
+        let joins_next = joins_forwards(joining_type) && next.is_some_and(|&t| joins_backwards(t));
+        forms[i] = Some(match (joins_previous, joins_next) {
+            (true, true) => "medi",
+            (true, false) => "fina",
+            (false, true) => "init",
+            (false, false) => "isol",
+        });
+    }
+
+    forms
+}
+
+fn joins_backwards(joining_type: JoiningType) -> bool {
+    matches!(joining_type, JoiningType::DualJoining | JoiningType::RightJoining | JoiningType::JoinCausing)
+}
+
+fn joins_forwards(joining_type: JoiningType) -> bool {
+    matches!(joining_type, JoiningType::DualJoining | JoiningType::LeftJoining | JoiningType::JoinCausing)
+}
+
+pub fn joining_script(text: &str) -> Option<&'static str> {
+    //The OpenType script tag of the first letter of a joining script, their lookups live under it
+    text.chars().find_map(|c| match c as u32 {
+        0x0600..=0x06FF | 0x0750..=0x077F | 0x0870..=0x08FF | 0xFB50..=0xFDFF | 0xFE70..=0xFEFF => Some("arab"),
+        0x0700..=0x074F | 0x0860..=0x086F => Some("syrc"),
+        0x07C0..=0x07FF => Some("nko "),
+        0x1800..=0x18AF => Some("mong"),
+        _ => None,
+    })
+}
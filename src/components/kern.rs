@@ -3,31 +3,166 @@ use std::io::{self, Seek, SeekFrom};
 use byteorder::{BigEndian, ReadBytesExt};
 use std::collections::HashMap;
 
-pub fn parse_kern_table(file: &mut File, glyph_indices: &[u32], kern_offset: u32) -> io::Result<i32> {
+pub fn parse_kern_table(file: &mut File, glyph_indices: &[u32], kern_offset: u32) -> io::Result<Vec<i32>> {
 
     //The kerning table contains values that control inter-character spacing for the glyphs in a font. 
     //Fonts containing CFF outlines are not supported by the 'kern' table and require use of the GPOS table to provide kerning.
 
+    //Returns one adjustment per glyph, applied after that glyph's advance.
+    //The last glyph has nothing to its right and always gets 0.
+    let subtables = read_kern_subtables(file, kern_offset)?;
+
+    let mut adjustments = vec![0; glyph_indices.len()];
+    for i in 0..glyph_indices.len().saturating_sub(1) {
+        let left = glyph_indices[i] as u16;
+        let right = glyph_indices[i + 1] as u16;
+
+        if glyph_indices[i] > u16::MAX as u32 || glyph_indices[i + 1] > u16::MAX as u32 {
+            println!(
+                "Warning: Glyph index out of range for kerning pair ({}, {})",
+                glyph_indices[i], glyph_indices[i + 1]
+            );
+            continue; // Skip invalid glyph indices
+        }
+
+        let kerning_value = accumulate_kerning(&subtables, left, right);
+        if kerning_value != 0 {
+            println!(
+                "Debug: Kerning pair ({}, {}) has adjustment: {}",
+                left, right, kerning_value
+            );
+            adjustments[i] = kerning_value;
+        }
+    }
+
+    Ok(adjustments)
+}
+
+//Coverage of a subtable, normalised from the Microsoft (version 0) and Apple (version 1) layouts
+struct KernSubtable {
+    horizontal: bool,
+    minimum: bool,
+    cross_stream: bool,
+    override_values: bool,
+    data: KernData,
+}
+
+enum KernData {
+    //Format 0: sorted list of glyph pairs and their values
+    Pairs(HashMap<(u16, u16), i16>),
+    //Format 2: class of the left glyph selects a row, class of the right glyph a column
+    Classes {
+        left: ClassTable,
+        right: ClassTable,
+        array_offset: u16,
+        values: Vec<i16>,
+    },
+}
+
+//Format 2 class table: firstGlyph, nGlyphs and a class value per glyph.
+//Left class values are byte offsets of a row from the start of the subtable,
+//right class values are byte offsets of a column within the row.
+struct ClassTable {
+    first_glyph: u16,
+    classes: Vec<u16>,
+}
+
+impl ClassTable {
+    fn class(&self, glyph: u16) -> u16 {
+        glyph
+            .checked_sub(self.first_glyph)
+            .and_then(|index| self.classes.get(index as usize))
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+impl KernData {
+    fn kerning(&self, left: u16, right: u16) -> Option<i16> {
+        match self {
+            KernData::Pairs(pairs) => pairs.get(&(left, right)).copied(),
+            KernData::Classes { left: left_table, right: right_table, array_offset, values } => {
+                // Glyphs outside the class tables get class 0 which points before the array, no kerning
+                let offset = left_table.class(left) as usize + right_table.class(right) as usize;
+                let byte_index = offset.checked_sub(*array_offset as usize)?;
+                values.get(byte_index / 2).copied()
+            }
+        }
+    }
+}
+
+fn accumulate_kerning(subtables: &[KernSubtable], left: u16, right: u16) -> i32 {
+    //Subtables are applied in order:
+    //only horizontal, non cross-stream subtables change the width,
+    //override subtables replace the accumulated value instead of adding to it,
+    //minimum subtables limit how far the accumulated value may go in the direction of their value
+    let mut accumulated: i32 = 0;
+    for subtable in subtables {
+        if !subtable.horizontal || subtable.cross_stream {
+            continue;
+        }
+        let Some(value) = subtable.data.kerning(left, right) else { continue };
+        let value = value as i32;
+
+        if subtable.minimum {
+            accumulated = if value < 0 { accumulated.max(value) } else { accumulated.min(value) };
+        } else if subtable.override_values {
+            accumulated = value;
+        } else {
+            accumulated += value;
+        }
+    }
+    accumulated
+}
+
+fn read_kern_subtables(file: &mut File, kern_offset: u32) -> io::Result<Vec<KernSubtable>> {
     // Seek to the start of the kern table
     file.seek(SeekFrom::Start(kern_offset as u64))?;
 
-    // Read kern table header
-    let _version = file.read_u16::<BigEndian>()?;
-    let n_tables = file.read_u16::<BigEndian>()?;
+    // Read kern table header, Microsoft tables start with a 16 bit version 0,
+    // Apple tables with a 32 bit version 1.0 (0x00010000) followed by a 32 bit nTables
+    let version = file.read_u16::<BigEndian>()?;
+    let is_apple = version == 1;
+    let n_tables = if is_apple {
+        let _version_fraction = file.read_u16::<BigEndian>()?;
+        file.read_u32::<BigEndian>()?
+    } else {
+        file.read_u16::<BigEndian>()? as u32
+    };
 
-    // Loop through subtables to find a usable one (Format 0)
-    let mut kerning_pairs = HashMap::new();
+    // Loop through subtables and keep the formats we understand (0 and 2)
+    let mut subtables = Vec::new();
     for _ in 0..n_tables {
-        let _version = file.read_u16::<BigEndian>()?;
-        let length = file.read_u16::<BigEndian>()?;
-        let coverage = file.read_u16::<BigEndian>()?;
-        let format = (coverage >> 8) & 0xFF;
+        let subtable_start = file.stream_position()?;
+        let (length, format, horizontal, minimum, cross_stream, override_values) = if is_apple {
+            // Apple subtable header: length (32), coverage (16), tupleIndex (16)
+            // coverage: 0x8000 vertical, 0x4000 cross-stream, 0x2000 variation, low byte format
+            let length = file.read_u32::<BigEndian>()?;
+            let coverage = file.read_u16::<BigEndian>()?;
+            let _tuple_index = file.read_u16::<BigEndian>()?;
+            if coverage & 0x2000 != 0 {
+                println!("Debug: Skipping kern variation subtable");
+            }
+            let horizontal = coverage & 0x8000 == 0 && coverage & 0x2000 == 0;
+            (length, coverage & 0xFF, horizontal, false, coverage & 0x4000 != 0, false)
+        } else {
+            // Microsoft subtable header: version (16), length (16), coverage (16)
+            // coverage: 0x01 horizontal, 0x02 minimum, 0x04 cross-stream, 0x08 override, high byte format
+            let _version = file.read_u16::<BigEndian>()?;
+            let length = file.read_u16::<BigEndian>()?;
+            let coverage = file.read_u16::<BigEndian>()?;
+            let flags = coverage & 0xFF;
+            (length as u32, (coverage >> 8) & 0xFF, flags & 0x01 != 0, flags & 0x02 != 0, flags & 0x04 != 0, flags & 0x08 != 0)
+        };
+        let header_size = file.stream_position()? - subtable_start;
+        let mut subtable_end = subtable_start + length as u64;
 
         if format == 0 {
             // Parse format 0 subtable only one supported by windows
             let n_pairs = file.read_u16::<BigEndian>()?;
             file.seek(SeekFrom::Current(6))?; // Skip searchRange, entrySelector, rangeShift
 
+            let mut kerning_pairs = HashMap::new();
             for _ in 0..n_pairs {
                 let left = file.read_u16::<BigEndian>()?;
                 let right = file.read_u16::<BigEndian>()?;
@@ -35,35 +170,47 @@ pub fn parse_kern_table(file: &mut File, glyph_indices: &[u32], kern_offset: u32
 
                 kerning_pairs.insert((left, right), value);
             }
-        } else {
-            // Skip unsupported subtable formats
-            file.seek(SeekFrom::Current((length - 6) as i64))?;
-        }
-    }
+            subtables.push(KernSubtable { horizontal, minimum, cross_stream, override_values, data: KernData::Pairs(kerning_pairs) });
+            // Large format 0 subtables overflow the 16 bit length, use the pair count instead
+            subtable_end = subtable_start + header_size + 8 + n_pairs as u64 * 6;
+        } else if format == 2 {
+            // Parse format 2 subtable, all offsets are from the start of the subtable
+            let _row_width = file.read_u16::<BigEndian>()?;
+            let left_class_offset = file.read_u16::<BigEndian>()?;
+            let right_class_offset = file.read_u16::<BigEndian>()?;
+            let array_offset = file.read_u16::<BigEndian>()?;
 
-    // Calculate kerning adjustments
-    let mut total_kerning = 0;
-    for i in 0..glyph_indices.len() - 1 {
-        let left = glyph_indices[i] as u16;
-        let right = glyph_indices[i + 1] as u16;
+            let left = read_class_table(file, subtable_start + left_class_offset as u64)?;
+            let right = read_class_table(file, subtable_start + right_class_offset as u64)?;
 
-        if glyph_indices[i] > u16::MAX as u32 || glyph_indices[i + 1] > u16::MAX as u32 {
-            println!(
-                "Warning: Glyph index out of range for kerning pair ({}, {})",
-                glyph_indices[i], glyph_indices[i + 1]
-            );
-            continue; // Skip invalid glyph indices
+            // The array runs to the end of the subtable
+            let value_count = (length as u64).saturating_sub(array_offset as u64) / 2;
+            file.seek(SeekFrom::Start(subtable_start + array_offset as u64))?;
+            let mut values = Vec::new();
+            for _ in 0..value_count {
+                values.push(file.read_i16::<BigEndian>()?);
+            }
+            println!("Debug: kern format 2 subtable with {} values", values.len());
+            let data = KernData::Classes { left, right, array_offset, values };
+            subtables.push(KernSubtable { horizontal, minimum, cross_stream, override_values, data });
+        } else {
+            println!("Debug: Unsupported kern subtable format: {}", format);
         }
 
-        if let Some(kerning_value) = kerning_pairs.get(&(left, right)) {
-            println!(
-                "Debug: Kerning pair ({}, {}) has adjustment: {}",
-                left, right, kerning_value
-            );
-            total_kerning += *kerning_value as i32;
-        }
+        // Continue with the next subtable
+        file.seek(SeekFrom::Start(subtable_end))?;
     }
 
-    Ok(total_kerning)
+    Ok(subtables)
 }
 
+fn read_class_table(file: &mut File, offset: u64) -> io::Result<ClassTable> {
+    file.seek(SeekFrom::Start(offset))?;
+    let first_glyph = file.read_u16::<BigEndian>()?;
+    let n_glyphs = file.read_u16::<BigEndian>()?;
+    let mut classes = Vec::new();
+    for _ in 0..n_glyphs {
+        classes.push(file.read_u16::<BigEndian>()?);
+    }
+    Ok(ClassTable { first_glyph, classes })
+}
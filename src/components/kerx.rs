@@ -0,0 +1,268 @@
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom};
+use byteorder::{BigEndian, ReadBytesExt};
+use crate::components::aat::{self, StateTable};
+use crate::components::glyf;
+use crate::components::layout::GlyphAdjustment;
+
+//The extended kerning table of Apple fonts. Like 'kern' it is a list of subtables,
+//with 32 bit headers and AAT lookup tables instead of plain class arrays.
+//Formats 0, 2 and 6 adjust the advance between pairs, format 4 attaches glyphs to each other.
+
+//Where to find outline points and anchors for format 4 attachments
+pub struct AttachmentSources {
+    pub glyf_offset: u32,
+    pub loca_offset: u32,
+    pub head_offset: u32,
+    pub ankr_offset: u32,
+}
+
+pub fn apply_kerx(
+    file: &mut File,
+    kerx_offset: u32,
+    glyph_indices: &[u32],
+    advance_widths: &[u16],
+    num_glyphs: u16,
+    sources: &AttachmentSources,
+    adjustments: &mut [GlyphAdjustment],
+) -> io::Result<()> {
+    //Adds the kerning and attachments to the adjustments, one per glyph
+
+    // Header: version (16), padding (16), nTables (32)
+    file.seek(SeekFrom::Start(kerx_offset as u64))?;
+    let _version = file.read_u16::<BigEndian>()?;
+    let _padding = file.read_u16::<BigEndian>()?;
+    let n_tables = file.read_u32::<BigEndian>()?;
+
+    let mut subtable_start = kerx_offset + 8;
+    for _ in 0..n_tables {
+        // Subtable header: length (32), coverage (32), tupleCount (32)
+        // coverage: 0x80000000 vertical, 0x40000000 cross-stream, 0x20000000 variation, low byte format
+        file.seek(SeekFrom::Start(subtable_start as u64))?;
+        let length = file.read_u32::<BigEndian>()?;
+        let coverage = file.read_u32::<BigEndian>()?;
+        let tuple_count = file.read_u32::<BigEndian>()?;
+        let format = coverage & 0xFF;
+        let vertical = coverage & 0x8000_0000 != 0;
+        let cross_stream = coverage & 0x4000_0000 != 0;
+
+        if vertical || coverage & 0x2000_0000 != 0 || tuple_count != 0 {
+            println!("Debug: Skipping vertical or variation kerx subtable");
+        } else if format == 4 {
+            apply_attachment(file, subtable_start, glyph_indices, advance_widths, num_glyphs, sources, adjustments)?;
+        } else if !cross_stream {
+            for i in 0..glyph_indices.len().saturating_sub(1) {
+                let left = glyph_indices[i];
+                let right = glyph_indices[i + 1];
+                let value = match format {
+                    0 => pair_value(file, subtable_start, left, right)?.map(i32::from),
+                    2 => class_value(file, subtable_start, left, right, num_glyphs)?.map(i32::from),
+                    6 => index_value(file, subtable_start, left, right, num_glyphs)?,
+                    _ => None,
+                };
+                if let Some(value) = value {
+                    println!("Debug: kerx pair ({}, {}) has adjustment: {}", left, right, value);
+                    adjustments[i].x_advance += value;
+                }
+            }
+            if ![0, 2, 6].contains(&format) {
+                println!("Debug: Unsupported kerx subtable format: {}", format);
+            }
+        }
+
+        subtable_start += length;
+    }
+
+    Ok(())
+}
+
+fn pair_value(file: &mut File, subtable: u32, left: u32, right: u32) -> io::Result<Option<i16>> {
+    // Format 0: nPairs, searchRange, entrySelector, rangeShift (all 32 bit), then sorted pairs
+    file.seek(SeekFrom::Start(subtable as u64 + 12))?;
+    let n_pairs = file.read_u32::<BigEndian>()?;
+    let pairs_offset = subtable as u64 + 28;
+
+    // Pairs are sorted by the combined (left, right) key, binary search them
+    let key = (left << 16) | right;
+    let (mut low, mut high) = (0u32, n_pairs);
+    while low < high {
+        let mid = (low + high) / 2;
+        file.seek(SeekFrom::Start(pairs_offset + mid as u64 * 6))?;
+        let pair_left = file.read_u16::<BigEndian>()? as u32;
+        let pair_right = file.read_u16::<BigEndian>()? as u32;
+        let pair_key = (pair_left << 16) | pair_right;
+        if pair_key == key {
+            return Ok(Some(file.read_i16::<BigEndian>()?));
+        } else if pair_key < key {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    Ok(None)
+}
+
+fn class_value(file: &mut File, subtable: u32, left: u32, right: u32, num_glyphs: u16) -> io::Result<Option<i16>> {
+    // Format 2: rowWidth, leftClassTable, rightClassTable, kerningArray (32 bit, from the subtable start)
+    // The sum of the left and right class values indexes the kerning array
+    file.seek(SeekFrom::Start(subtable as u64 + 12))?;
+    let _row_width = file.read_u32::<BigEndian>()?;
+    let left_table = subtable + file.read_u32::<BigEndian>()?;
+    let right_table = subtable + file.read_u32::<BigEndian>()?;
+    let array = subtable + file.read_u32::<BigEndian>()?;
+
+    let left_class = aat::lookup_value(file, left_table, left, num_glyphs, 2)?.unwrap_or(0);
+    let right_class = aat::lookup_value(file, right_table, right, num_glyphs, 2)?.unwrap_or(0);
+    file.seek(SeekFrom::Start(array as u64 + (left_class + right_class) as u64 * 2))?;
+    let value = file.read_i16::<BigEndian>()?;
+    Ok((value != 0).then_some(value))
+}
+
+fn index_value(file: &mut File, subtable: u32, left: u32, right: u32, num_glyphs: u16) -> io::Result<Option<i32>> {
+    // Format 6: flags, rowCount, columnCount, rowIndexTable, columnIndexTable, kerningArray
+    // flags bit 0 means the index lookup tables and the kerning array hold 32 bit values
+    file.seek(SeekFrom::Start(subtable as u64 + 12))?;
+    let flags = file.read_u32::<BigEndian>()?;
+    let _row_count = file.read_u16::<BigEndian>()?;
+    let _column_count = file.read_u16::<BigEndian>()?;
+    let row_table = subtable + file.read_u32::<BigEndian>()?;
+    let column_table = subtable + file.read_u32::<BigEndian>()?;
+    let array = subtable + file.read_u32::<BigEndian>()?;
+
+    let value_size = if flags & 1 != 0 { 4 } else { 2 };
+    let Some(row) = aat::lookup_value(file, row_table, left, num_glyphs, value_size)? else { return Ok(None) };
+    let Some(column) = aat::lookup_value(file, column_table, right, num_glyphs, value_size)? else { return Ok(None) };
+    file.seek(SeekFrom::Start(array as u64 + (row as u64 + column as u64) * value_size as u64))?;
+    let value = if value_size == 4 { file.read_i32::<BigEndian>()? } else { file.read_i16::<BigEndian>()? as i32 };
+    Ok((value != 0).then_some(value))
+}
+
+fn apply_attachment(
+    file: &mut File,
+    subtable: u32,
+    glyph_indices: &[u32],
+    advance_widths: &[u16],
+    num_glyphs: u16,
+    sources: &AttachmentSources,
+    adjustments: &mut [GlyphAdjustment],
+) -> io::Result<()> {
+    //Format 4: a state machine marks a glyph and later attaches the current glyph to it.
+    //The action moves the current glyph so its point lands on the point of the marked glyph.
+    const MARK: u16 = 0x8000;
+    const DONT_ADVANCE: u16 = 0x4000;
+
+    let machine_offset = subtable + 12;
+    let machine = StateTable::read(file, machine_offset, num_glyphs)?;
+    file.seek(SeekFrom::Start(machine_offset as u64 + 16))?;
+    let flags = file.read_u32::<BigEndian>()?;
+    let action_type = flags >> 30;
+    let action_table = machine_offset + (flags & 0x00FF_FFFF);
+
+    let mut state: u16 = 0;
+    let mut mark: Option<usize> = None;
+    let mut i = 0;
+    let mut stalled = 0;
+    loop {
+        let glyph = glyph_indices.get(i).copied();
+        let class = machine.glyph_class(file, glyph)?;
+        let entry = machine.entry_offset(file, state, class, 6)?;
+        file.seek(SeekFrom::Start(entry as u64))?;
+        let new_state = file.read_u16::<BigEndian>()?;
+        let entry_flags = file.read_u16::<BigEndian>()?;
+        let action_index = file.read_u16::<BigEndian>()?;
+
+        if let (Some(marked), Some(current)) = (mark, glyph) {
+            if action_index != 0xFFFF {
+                let points = attachment_points(file, action_type, action_table, action_index, glyph_indices[marked], current, sources)?;
+                if let Some(((mark_x, mark_y), (current_x, current_y))) = points {
+                    // The marked glyph's origin relative to the current pen position
+                    let pen_distance: i32 = advance_widths[marked..i].iter().map(|&w| w as i32).sum::<i32>()
+                        + adjustments[marked..i].iter().map(|a| a.x_advance).sum::<i32>();
+                    adjustments[i].x_placement = adjustments[marked].x_placement - pen_distance + (mark_x - current_x).round() as i32;
+                    adjustments[i].y_placement = adjustments[marked].y_placement + (mark_y - current_y).round() as i32;
+                }
+            }
+        }
+        if entry_flags & MARK != 0 && glyph.is_some() {
+            mark = Some(i);
+        }
+
+        state = new_state;
+        if glyph.is_none() {
+            break;
+        }
+        // A state machine that keeps asking not to advance would never finish
+        if entry_flags & DONT_ADVANCE == 0 || stalled >= 8 {
+            i += 1;
+            stalled = 0;
+        } else {
+            stalled += 1;
+        }
+    }
+    Ok(())
+}
+
+type AttachmentPoints = ((f32, f32), (f32, f32));
+
+fn attachment_points(
+    file: &mut File,
+    action_type: u32,
+    action_table: u32,
+    action_index: u16,
+    marked_glyph: u32,
+    current_glyph: u32,
+    sources: &AttachmentSources,
+) -> io::Result<Option<AttachmentPoints>> {
+    match action_type {
+        0 => {
+            // Control point actions: outline point numbers in the marked and the current glyph
+            file.seek(SeekFrom::Start(action_table as u64 + action_index as u64 * 4))?;
+            let mark_point = file.read_u16::<BigEndian>()?;
+            let current_point = file.read_u16::<BigEndian>()?;
+            let mark = glyf::get_glyph_point(file, sources.glyf_offset, sources.loca_offset, sources.head_offset, marked_glyph, mark_point)?;
+            let current = glyf::get_glyph_point(file, sources.glyf_offset, sources.loca_offset, sources.head_offset, current_glyph, current_point)?;
+            Ok(mark.zip(current))
+        }
+        1 => {
+            // Anchor point actions: anchor numbers in the 'ankr' table
+            if sources.ankr_offset == 0 {
+                return Ok(None);
+            }
+            file.seek(SeekFrom::Start(action_table as u64 + action_index as u64 * 4))?;
+            let mark_anchor = file.read_u16::<BigEndian>()?;
+            let current_anchor = file.read_u16::<BigEndian>()?;
+            let mark = read_anchor(file, sources.ankr_offset, marked_glyph, mark_anchor)?;
+            let current = read_anchor(file, sources.ankr_offset, current_glyph, current_anchor)?;
+            Ok(mark.zip(current))
+        }
+        2 => {
+            // Control point coordinate actions: the coordinates are stored directly
+            file.seek(SeekFrom::Start(action_table as u64 + action_index as u64 * 8))?;
+            let mark_x = file.read_i16::<BigEndian>()? as f32;
+            let mark_y = file.read_i16::<BigEndian>()? as f32;
+            let current_x = file.read_i16::<BigEndian>()? as f32;
+            let current_y = file.read_i16::<BigEndian>()? as f32;
+            Ok(Some(((mark_x, mark_y), (current_x, current_y))))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn read_anchor(file: &mut File, ankr_offset: u32, glyph: u32, anchor_index: u16) -> io::Result<Option<(f32, f32)>> {
+    // ankr header: version, flags, lookupTableOffset (32), glyphDataTableOffset (32)
+    file.seek(SeekFrom::Start(ankr_offset as u64 + 4))?;
+    let lookup_table = ankr_offset + file.read_u32::<BigEndian>()?;
+    let glyph_data = ankr_offset + file.read_u32::<BigEndian>()?;
+
+    // The lookup gives the offset of the glyph's anchor list: numPoints (32), then x, y pairs
+    let Some(anchors_offset) = aat::lookup_value(file, lookup_table, glyph, u16::MAX, 2)? else { return Ok(None) };
+    file.seek(SeekFrom::Start((glyph_data + anchors_offset) as u64))?;
+    let num_points = file.read_u32::<BigEndian>()?;
+    if anchor_index as u32 >= num_points {
+        return Ok(None);
+    }
+    file.seek(SeekFrom::Current(anchor_index as i64 * 4))?;
+    let x = file.read_i16::<BigEndian>()? as f32;
+    let y = file.read_i16::<BigEndian>()? as f32;
+    Ok(Some((x, y)))
+}
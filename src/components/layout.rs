@@ -0,0 +1,114 @@
+use std::ops::Range;
+use crate::components::gpos::ValueRecord;
+
+//Places the glyphs of a run along the baseline in font units.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PositionedGlyph {
+    pub glyph_index: u32,
+    pub x: i32,
+    pub y: i32,
+}
+
+//Position changes from kerning, x_advance changes the distance to the next glyph
+//while x_placement and y_placement only move the glyph itself
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct GlyphAdjustment {
+    pub x_placement: i32,
+    pub y_placement: i32,
+    pub x_advance: i32,
+}
+
+impl GlyphAdjustment {
+    pub fn add(&mut self, value: &ValueRecord) {
+        self.x_placement += value.x_placement as i32;
+        self.y_placement += value.y_placement as i32;
+        self.x_advance += value.x_advance as i32;
+    }
+}
+
+pub fn position_glyphs(glyph_indices: &[u32], advance_widths: &[u16], adjustments: &[GlyphAdjustment]) -> Vec<PositionedGlyph> {
+    //Each glyph starts where the previous advance, plus the kerning after it, ended
+    let mut glyphs = Vec::new();
+    let mut pen_x: i32 = 0;
+    for (i, &glyph_index) in glyph_indices.iter().enumerate() {
+        let adjustment = adjustments.get(i).copied().unwrap_or_default();
+        glyphs.push(PositionedGlyph {
+            glyph_index,
+            x: pen_x + adjustment.x_placement,
+            y: adjustment.y_placement,
+        });
+        pen_x += advance_widths.get(i).copied().unwrap_or(0) as i32;
+        pen_x += adjustment.x_advance;
+    }
+    glyphs
+}
+
+pub fn position_glyphs_vertical(glyph_indices: &[u32], advance_widths: &[u16], advance_heights: &[u16], origins: &[i32], column_width: i32) -> Vec<PositionedGlyph> {
+    //Glyphs are centred in a column of the given width and stacked downwards from y = 0,
+    //each with its vertical origin (at origins[i] above its baseline) on the pen
+    let mut glyphs = Vec::new();
+    let mut pen_y: i32 = 0;
+    for (i, &glyph_index) in glyph_indices.iter().enumerate() {
+        let advance_width = advance_widths.get(i).copied().unwrap_or(0) as i32;
+        glyphs.push(PositionedGlyph {
+            glyph_index,
+            x: (column_width - advance_width) / 2,
+            y: pen_y - origins.get(i).copied().unwrap_or(0),
+        });
+        pen_y -= advance_heights.get(i).copied().unwrap_or(0) as i32;
+    }
+    glyphs
+}
+
+pub fn split_lines(text: &str) -> Vec<(usize, &str)> {
+    //Splits the text at line feeds, carriage returns and line separators (CR LF is one break).
+    //Returns every line with the index of its first character in the text.
+    let mut lines = Vec::new();
+    let mut line_start = (0, 0);
+    let mut chars = text.char_indices().enumerate().peekable();
+    while let Some((char_index, (byte_index, c))) = chars.next() {
+        if !matches!(c, '\n' | '\r' | '\u{2028}') {
+            continue;
+        }
+        lines.push((line_start.0, &text[line_start.1..byte_index]));
+        let mut next = (char_index + 1, byte_index + c.len_utf8());
+        if c == '\r' && chars.peek().is_some_and(|&(_, (_, c))| c == '\n') {
+            chars.next();
+            next = (char_index + 2, byte_index + 2);
+        }
+        line_start = next;
+    }
+    // A break at the very end closes the last line instead of starting an empty one
+    if lines.is_empty() || line_start.1 < text.len() {
+        lines.push((line_start.0, &text[line_start.1..]));
+    }
+    lines
+}
+
+pub fn position_lines(glyph_indices: &[u32], advance_widths: &[u16], adjustments: &[GlyphAdjustment], lines: &[Range<usize>], line_spacing: i32) -> Vec<PositionedGlyph> {
+    //Every line starts at x = 0, each baseline line_spacing below the one before
+    let mut glyphs = Vec::new();
+    for (k, line) in lines.iter().enumerate() {
+        let placed = position_glyphs(&glyph_indices[line.clone()], &advance_widths[line.clone()], &adjustments[line.clone()]);
+        glyphs.extend(placed.into_iter().map(|glyph| PositionedGlyph { y: glyph.y - k as i32 * line_spacing, ..glyph }));
+    }
+    glyphs
+}
+
+pub fn position_columns(glyph_indices: &[u32], advance_widths: &[u16], advance_heights: &[u16], origins: &[i32], column_width: i32, lines: &[Range<usize>], column_spacing: i32) -> Vec<PositionedGlyph> {
+    //Vertical lines are columns read from right to left, the first line is the rightmost column
+    let mut glyphs = Vec::new();
+    for (k, line) in lines.iter().enumerate() {
+        let offset = (lines.len() - 1 - k) as i32 * column_spacing;
+        let placed = position_glyphs_vertical(
+            &glyph_indices[line.clone()],
+            &advance_widths[line.clone()],
+            &advance_heights[line.clone()],
+            &origins[line.clone()],
+            column_width,
+        );
+        glyphs.extend(placed.into_iter().map(|glyph| PositionedGlyph { x: glyph.x + offset, ..glyph }));
+    }
+    glyphs
+}
@@ -0,0 +1,380 @@
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom};
+use byteorder::{BigEndian, ReadBytesExt};
+use crate::components::aat::{self, StateTable, DELETED_GLYPH};
+
+//The extended glyph metamorphosis table of Apple fonts, their equivalent of GSUB.
+//A list of chains, each a list of subtables that run when their feature flags are on.
+//Apart from the noncontextual subtable they are state machines walking the glyph run.
+
+//Subtable types
+const REARRANGEMENT: u32 = 0;
+const CONTEXTUAL: u32 = 1;
+const LIGATURE: u32 = 2;
+const NONCONTEXTUAL: u32 = 4;
+const INSERTION: u32 = 5;
+
+//Entry flags shared by the state machine subtables
+const DONT_ADVANCE: u16 = 0x4000;
+
+//A state machine asking not to advance more often than this in a row is moved on anyway
+const MAX_STALL: u32 = 8;
+
+//A glyph of the run and the index of the input glyph it came from, like in GSUB
+type RunGlyph = (u32, usize);
+
+pub fn apply_morx(file: &mut File, morx_offset: u32, glyph_indices: &[u32], num_glyphs: u16) -> io::Result<(Vec<u32>, Vec<usize>)> {
+    //Runs every chain with its default feature flags.
+    //Returns the new glyph run and for every glyph the index of the input glyph it started as.
+    let mut run: Vec<RunGlyph> = glyph_indices.iter().enumerate().map(|(i, &g)| (g, i)).collect();
+
+    // Header: version (16), unused (16), nChains (32)
+    file.seek(SeekFrom::Start(morx_offset as u64))?;
+    let _version = file.read_u16::<BigEndian>()?;
+    let _unused = file.read_u16::<BigEndian>()?;
+    let n_chains = file.read_u32::<BigEndian>()?;
+
+    let mut chain_start = morx_offset + 8;
+    for _ in 0..n_chains {
+        // Chain header: defaultFlags, chainLength, nFeatureEntries, nSubtables (all 32 bit)
+        file.seek(SeekFrom::Start(chain_start as u64))?;
+        let default_flags = file.read_u32::<BigEndian>()?;
+        let chain_length = file.read_u32::<BigEndian>()?;
+        let n_feature_entries = file.read_u32::<BigEndian>()?;
+        let n_subtables = file.read_u32::<BigEndian>()?;
+
+        // Feature entries are 12 bytes each, the subtables follow them
+        let mut subtable_start = chain_start + 16 + n_feature_entries * 12;
+        for _ in 0..n_subtables {
+            // Subtable header: length, coverage, subFeatureFlags (all 32 bit)
+            // coverage: 0x80000000 vertical only, 0x20000000 any orientation, low byte type
+            file.seek(SeekFrom::Start(subtable_start as u64))?;
+            let length = file.read_u32::<BigEndian>()?;
+            let coverage = file.read_u32::<BigEndian>()?;
+            let sub_feature_flags = file.read_u32::<BigEndian>()?;
+            let subtable_type = coverage & 0xFF;
+            let vertical_only = coverage & 0x8000_0000 != 0 && coverage & 0x2000_0000 == 0;
+
+            if sub_feature_flags & default_flags != 0 && !vertical_only {
+                let body = subtable_start + 12;
+                match subtable_type {
+                    REARRANGEMENT => apply_rearrangement(file, body, num_glyphs, &mut run)?,
+                    CONTEXTUAL => apply_contextual(file, body, num_glyphs, &mut run)?,
+                    LIGATURE => apply_ligature(file, body, num_glyphs, &mut run)?,
+                    NONCONTEXTUAL => apply_noncontextual(file, body, num_glyphs, &mut run)?,
+                    INSERTION => apply_insertion(file, body, num_glyphs, &mut run)?,
+                    _ => println!("Debug: Unsupported morx subtable type: {}", subtable_type),
+                }
+                run.retain(|&(g, _)| g != DELETED_GLYPH);
+            }
+
+            subtable_start += length;
+        }
+
+        chain_start += chain_length;
+    }
+
+    let glyphs: Vec<u32> = run.iter().map(|&(g, _)| g).collect();
+    let clusters = run.iter().map(|&(_, c)| c).collect();
+    Ok((glyphs, clusters))
+}
+
+//Reads an entry of the state machine: newState, flags and the subtable specific values after them
+fn read_entry(file: &mut File, machine: &StateTable, state: u16, glyph: Option<u32>, extra_values: u32) -> io::Result<(u16, u16, Vec<u16>)> {
+    let class = machine.glyph_class(file, glyph)?;
+    let entry = machine.entry_offset(file, state, class, 4 + extra_values * 2)?;
+    file.seek(SeekFrom::Start(entry as u64))?;
+    let new_state = file.read_u16::<BigEndian>()?;
+    let flags = file.read_u16::<BigEndian>()?;
+    let mut values = Vec::new();
+    for _ in 0..extra_values {
+        values.push(file.read_u16::<BigEndian>()?);
+    }
+    Ok((new_state, flags, values))
+}
+
+//Moves on to the next glyph unless the entry asked not to advance
+fn advance(i: &mut usize, flags: u16, stalled: &mut u32) {
+    if flags & DONT_ADVANCE == 0 || *stalled >= MAX_STALL {
+        *i += 1;
+        *stalled = 0;
+    } else {
+        *stalled += 1;
+    }
+}
+
+fn apply_noncontextual(file: &mut File, body: u32, num_glyphs: u16, run: &mut [RunGlyph]) -> io::Result<()> {
+    //A lookup table from glyph to replacement glyph
+    for glyph in run.iter_mut() {
+        if glyph.0 == DELETED_GLYPH {
+            continue;
+        }
+        if let Some(replacement) = aat::lookup_value(file, body, glyph.0, num_glyphs, 2)? {
+            glyph.0 = replacement;
+        }
+    }
+    Ok(())
+}
+
+fn apply_rearrangement(file: &mut File, body: u32, num_glyphs: u16, run: &mut [RunGlyph]) -> io::Result<()> {
+    //Marks a first and a last glyph, then the verb of an entry reorders the glyphs between them
+    const MARK_FIRST: u16 = 0x8000;
+    const MARK_LAST: u16 = 0x2000;
+    const VERB: u16 = 0x000F;
+    //Number of glyphs moved from the start (high nibble) and the end (low nibble) of the range,
+    //3 means two glyphs in reversed order
+    const VERB_MAP: [u8; 16] = [0x00, 0x10, 0x01, 0x11, 0x20, 0x30, 0x02, 0x03, 0x12, 0x13, 0x21, 0x31, 0x22, 0x32, 0x23, 0x33];
+
+    let machine = StateTable::read(file, body, num_glyphs)?;
+    let (mut state, mut i, mut stalled) = (0u16, 0usize, 0u32);
+    let (mut first, mut last) = (0usize, 0usize);
+    loop {
+        let glyph = run.get(i).map(|g| g.0);
+        let (new_state, flags, _) = read_entry(file, &machine, state, glyph, 0)?;
+
+        if flags & MARK_FIRST != 0 {
+            first = i;
+        }
+        if flags & MARK_LAST != 0 {
+            last = (i + 1).min(run.len());
+        }
+
+        let verb = VERB_MAP[(flags & VERB) as usize];
+        if verb != 0 && first < last {
+            let l = ((verb >> 4) as usize).min(2);
+            let r = ((verb & 0x0F) as usize).min(2);
+            if last - first >= l + r {
+                let range = &run[first..last];
+                let mut start_glyphs = range[..l].to_vec();
+                let middle = range[l..range.len() - r].to_vec();
+                let mut end_glyphs = range[range.len() - r..].to_vec();
+                if verb >> 4 == 3 {
+                    start_glyphs.reverse();
+                }
+                if verb & 0x0F == 3 {
+                    end_glyphs.reverse();
+                }
+                let reordered: Vec<RunGlyph> = end_glyphs.into_iter().chain(middle).chain(start_glyphs).collect();
+                run[first..last].copy_from_slice(&reordered);
+            }
+        }
+
+        state = new_state;
+        if glyph.is_none() {
+            break;
+        }
+        advance(&mut i, flags, &mut stalled);
+    }
+    Ok(())
+}
+
+fn apply_contextual(file: &mut File, body: u32, num_glyphs: u16, run: &mut [RunGlyph]) -> io::Result<()> {
+    //Entries may replace the marked glyph and the current glyph through one of the substitution lookups
+    const SET_MARK: u16 = 0x8000;
+
+    let machine = StateTable::read(file, body, num_glyphs)?;
+    file.seek(SeekFrom::Start(body as u64 + 16))?;
+    let substitution_table = body + file.read_u32::<BigEndian>()?;
+
+    let (mut state, mut i, mut stalled) = (0u16, 0usize, 0u32);
+    let mut mark: Option<usize> = None;
+    loop {
+        let glyph = run.get(i).map(|g| g.0);
+        let (new_state, flags, values) = read_entry(file, &machine, state, glyph, 2)?;
+        let (mark_index, current_index) = (values[0], values[1]);
+
+        if let Some(marked) = mark {
+            if mark_index != 0xFFFF {
+                substitute(file, substitution_table, mark_index, num_glyphs, &mut run[marked])?;
+            }
+        }
+        // At the end of the text the current glyph is the last one
+        let current = if glyph.is_some() { Some(i) } else { run.len().checked_sub(1) };
+        if let Some(current) = current {
+            if current_index != 0xFFFF {
+                substitute(file, substitution_table, current_index, num_glyphs, &mut run[current])?;
+            }
+        }
+        if flags & SET_MARK != 0 && glyph.is_some() {
+            mark = Some(i);
+        }
+
+        state = new_state;
+        if glyph.is_none() {
+            break;
+        }
+        advance(&mut i, flags, &mut stalled);
+    }
+    Ok(())
+}
+
+fn substitute(file: &mut File, substitution_table: u32, table_index: u16, num_glyphs: u16, glyph: &mut RunGlyph) -> io::Result<()> {
+    // The substitution table is an array of 32 bit offsets to lookup tables
+    file.seek(SeekFrom::Start(substitution_table as u64 + table_index as u64 * 4))?;
+    let lookup = substitution_table + file.read_u32::<BigEndian>()?;
+    if glyph.0 != DELETED_GLYPH {
+        if let Some(replacement) = aat::lookup_value(file, lookup, glyph.0, num_glyphs, 2)? {
+            glyph.0 = replacement;
+        }
+    }
+    Ok(())
+}
+
+fn apply_ligature(file: &mut File, body: u32, num_glyphs: u16, run: &mut [RunGlyph]) -> io::Result<()> {
+    //Entries push component glyphs on a stack, a ligature action then pops them, sums their
+    //component values into a ligature index and stores the ligature glyph on the first component.
+    //The other components become deleted glyphs.
+    const SET_COMPONENT: u16 = 0x8000;
+    const PERFORM_ACTION: u16 = 0x2000;
+    const ACTION_LAST: u32 = 0x8000_0000;
+    const ACTION_STORE: u32 = 0x4000_0000;
+    const ACTION_OFFSET: u32 = 0x3FFF_FFFF;
+    const MAX_COMPONENTS: usize = 64;
+
+    let machine = StateTable::read(file, body, num_glyphs)?;
+    file.seek(SeekFrom::Start(body as u64 + 16))?;
+    let lig_actions = body + file.read_u32::<BigEndian>()?;
+    let components = body + file.read_u32::<BigEndian>()?;
+    let ligatures = body + file.read_u32::<BigEndian>()?;
+
+    let (mut state, mut i, mut stalled) = (0u16, 0usize, 0u32);
+    let mut stack: Vec<usize> = Vec::new();
+    loop {
+        let glyph = run.get(i).map(|g| g.0);
+        let (new_state, flags, values) = read_entry(file, &machine, state, glyph, 1)?;
+        let action_index = values[0];
+
+        if flags & SET_COMPONENT != 0 && glyph.is_some() {
+            // Never push the same glyph twice when the machine did not advance
+            if stack.last() != Some(&i) {
+                stack.push(i);
+            }
+            if stack.len() > MAX_COMPONENTS {
+                stack.remove(0);
+            }
+        }
+
+        if flags & PERFORM_ACTION != 0 {
+            let mut action_offset = lig_actions as u64 + action_index as u64 * 4;
+            let mut ligature_index: u32 = 0;
+            let mut cursor = stack.len();
+            loop {
+                if cursor == 0 {
+                    stack.clear();
+                    break;
+                }
+                cursor -= 1;
+                let position = stack[cursor];
+
+                file.seek(SeekFrom::Start(action_offset))?;
+                let action = file.read_u32::<BigEndian>()?;
+                // The offset is a signed 30 bit value added to the glyph id
+                let mut offset = action & ACTION_OFFSET;
+                if offset & 0x2000_0000 != 0 {
+                    offset |= 0xC000_0000;
+                }
+                let component_index = run[position].0.wrapping_add(offset);
+                file.seek(SeekFrom::Start(components as u64 + component_index as u64 * 2))?;
+                ligature_index += file.read_u16::<BigEndian>()? as u32;
+
+                if action & (ACTION_STORE | ACTION_LAST) != 0 {
+                    file.seek(SeekFrom::Start(ligatures as u64 + ligature_index as u64 * 2))?;
+                    run[position].0 = file.read_u16::<BigEndian>()? as u32;
+                    // Delete the components after the ligature, the ligature stays on the stack
+                    while stack.len() - 1 > cursor {
+                        let component = stack.pop().unwrap_or(position);
+                        run[component].0 = DELETED_GLYPH;
+                    }
+                }
+
+                action_offset += 4;
+                if action & ACTION_LAST != 0 {
+                    break;
+                }
+            }
+        }
+
+        state = new_state;
+        if glyph.is_none() {
+            break;
+        }
+        advance(&mut i, flags, &mut stalled);
+    }
+    Ok(())
+}
+
+fn apply_insertion(file: &mut File, body: u32, num_glyphs: u16, run: &mut Vec<RunGlyph>) -> io::Result<()> {
+    //Entries insert glyphs from the insertion action list before or after the marked and the current glyph
+    const SET_MARK: u16 = 0x8000;
+    const CURRENT_INSERT_BEFORE: u16 = 0x0800;
+    const MARKED_INSERT_BEFORE: u16 = 0x0400;
+    const CURRENT_INSERT_COUNT: u16 = 0x03E0;
+    const MARKED_INSERT_COUNT: u16 = 0x001F;
+
+    let machine = StateTable::read(file, body, num_glyphs)?;
+    file.seek(SeekFrom::Start(body as u64 + 16))?;
+    let insertion_actions = body + file.read_u32::<BigEndian>()?;
+
+    let (mut state, mut i, mut stalled) = (0u16, 0usize, 0u32);
+    let mut mark: Option<usize> = None;
+    loop {
+        let glyph = run.get(i).map(|g| g.0);
+        let (new_state, flags, values) = read_entry(file, &machine, state, glyph, 2)?;
+        let (current_insert_index, marked_insert_index) = (values[0], values[1]);
+
+        if let Some(marked) = mark {
+            if marked_insert_index != 0xFFFF {
+                let count = flags & MARKED_INSERT_COUNT;
+                let glyphs = read_insertion(file, insertion_actions, marked_insert_index, count)?;
+                let at = if flags & MARKED_INSERT_BEFORE != 0 { marked } else { marked + 1 };
+                let cluster = run[marked].1;
+                run.splice(at..at, glyphs.iter().map(|&g| (g, cluster)));
+                // Everything from the insertion point on moved along
+                if i >= at {
+                    i += glyphs.len();
+                }
+                if flags & MARKED_INSERT_BEFORE != 0 {
+                    mark = Some(marked + glyphs.len());
+                }
+            }
+        }
+        if flags & SET_MARK != 0 && glyph.is_some() {
+            mark = Some(i);
+        }
+
+        if current_insert_index != 0xFFFF {
+            let count = (flags & CURRENT_INSERT_COUNT) >> 5;
+            let glyphs = read_insertion(file, insertion_actions, current_insert_index, count)?;
+            let before = flags & CURRENT_INSERT_BEFORE != 0 || i >= run.len();
+            let at = if before { i } else { i + 1 };
+            let cluster = run.get(i).or(run.last()).map(|g| g.1).unwrap_or(0);
+            run.splice(at..at, glyphs.iter().map(|&g| (g, cluster)));
+            if let Some(marked) = mark {
+                if marked >= at {
+                    mark = Some(marked + glyphs.len());
+                }
+            }
+            // Continue on the last inserted glyph (or the current glyph when inserted before it),
+            // unless the entry asked to look at the inserted glyphs again
+            if flags & DONT_ADVANCE == 0 {
+                i += glyphs.len();
+            }
+        }
+
+        state = new_state;
+        if glyph.is_none() {
+            break;
+        }
+        advance(&mut i, flags, &mut stalled);
+    }
+    Ok(())
+}
+
+fn read_insertion(file: &mut File, insertion_actions: u32, index: u16, count: u16) -> io::Result<Vec<u32>> {
+    file.seek(SeekFrom::Start(insertion_actions as u64 + index as u64 * 2))?;
+    let mut glyphs = Vec::new();
+    for _ in 0..count {
+        glyphs.push(file.read_u16::<BigEndian>()? as u32);
+    }
+    Ok(glyphs)
+}
@@ -0,0 +1,41 @@
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom};
+use byteorder::{BigEndian, ReadBytesExt};
+use crate::components::{otlayout, variations};
+
+//The metrics variations table holds the deltas of font wide metrics (hhea, OS/2, ...) of a variable font,
+//every metric is identified by a tag like 'cpht' for the cap height.
+
+pub fn metric_delta(file: &mut File, mvar_offset: u32, tag: &str, coords: &[f32]) -> io::Result<f32> {
+    // Header: majorVersion, minorVersion, reserved, valueRecordSize, valueRecordCount,
+    // itemVariationStoreOffset (16 bit), then the value records sorted by tag
+    file.seek(SeekFrom::Start(mvar_offset as u64 + 6))?;
+    let value_record_size = file.read_u16::<BigEndian>()?;
+    let value_record_count = file.read_u16::<BigEndian>()?;
+    let store_offset = file.read_u16::<BigEndian>()?;
+    if store_offset == 0 {
+        return Ok(0.0);
+    }
+
+    // ValueRecord: valueTag, deltaSetOuterIndex, deltaSetInnerIndex
+    for i in 0..value_record_count {
+        file.seek(SeekFrom::Start(mvar_offset as u64 + 12 + i as u64 * value_record_size as u64))?;
+        if otlayout::read_tag(file)? == tag {
+            let outer = file.read_u16::<BigEndian>()?;
+            let inner = file.read_u16::<BigEndian>()?;
+            return variations::item_delta(file, mvar_offset + store_offset as u32, outer, inner, coords);
+        }
+    }
+
+    Ok(0.0)
+}
+
+pub fn vary(file: &mut File, mvar_offset: u32, tag: &str, coords: &[f32], value: &mut i16) -> io::Result<()> {
+    //Adds the rounded delta of a metric to its default value
+    let delta = metric_delta(file, mvar_offset, tag, coords)?;
+    if delta != 0.0 {
+        println!("Debug: MVAR '{}' delta at instance: {}", tag, delta);
+    }
+    *value = (*value as f32 + delta).round() as i16;
+    Ok(())
+}
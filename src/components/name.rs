@@ -0,0 +1,60 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use byteorder::{BigEndian, ReadBytesExt};
+
+//The naming table holds the human readable strings of a font (family, style, instance names, ...)
+//in several platforms, encodings and languages.
+
+pub fn get_name(file: &mut File, name_offset: u32, name_id: u16) -> io::Result<Option<String>> {
+    //Returns the string of a name id, preferring Windows English, then any Windows or Unicode
+    //language, then Macintosh Roman
+    if name_offset == 0 {
+        return Ok(None);
+    }
+
+    // Header: version, count, storageOffset, then 12 byte NameRecords
+    file.seek(SeekFrom::Start(name_offset as u64 + 2))?;
+    let count = file.read_u16::<BigEndian>()?;
+    let storage_offset = file.read_u16::<BigEndian>()?;
+
+    // NameRecord: platformID, encodingID, languageID, nameID, length, stringOffset
+    let mut best: Option<(u8, u16, u16, u16)> = None;
+    for _ in 0..count {
+        let platform_id = file.read_u16::<BigEndian>()?;
+        let encoding_id = file.read_u16::<BigEndian>()?;
+        let language_id = file.read_u16::<BigEndian>()?;
+        let record_name_id = file.read_u16::<BigEndian>()?;
+        let length = file.read_u16::<BigEndian>()?;
+        let string_offset = file.read_u16::<BigEndian>()?;
+        if record_name_id != name_id {
+            continue;
+        }
+
+        // Lower rank is better
+        let rank = match (platform_id, encoding_id, language_id) {
+            (3, 1 | 10, 0x0409) => 0,
+            (3, 1 | 10, _) => 1,
+            (0, _, _) => 2,
+            (1, 0, _) => 3,
+            _ => continue,
+        };
+        if best.is_none_or(|(best_rank, ..)| rank < best_rank) {
+            best = Some((rank, platform_id, length, string_offset));
+        }
+    }
+
+    let Some((_, platform_id, length, string_offset)) = best else { return Ok(None) };
+    file.seek(SeekFrom::Start(name_offset as u64 + storage_offset as u64 + string_offset as u64))?;
+    let mut bytes = vec![0; length as usize];
+    file.read_exact(&mut bytes)?;
+
+    let name = if platform_id == 1 {
+        // Macintosh Roman, the ASCII range is all names use in practice
+        bytes.iter().map(|&b| b as char).collect()
+    } else {
+        // Windows and Unicode platform strings are UTF-16BE
+        let units: Vec<u16> = bytes.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+        String::from_utf16_lossy(&units)
+    };
+    Ok(Some(name))
+}
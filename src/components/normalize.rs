@@ -0,0 +1,62 @@
+use std::fs::File;
+use std::io;
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+use crate::components::cmap;
+
+//Unicode normalization of the input text, so "é" measures the same whether it was pasted as one code point
+//or as "e" and a combining acute, and the fallback for letters the font has no precomposed glyph for.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Normalization {
+    None,
+    Nfc,
+    Nfd,
+    Nfkc,
+    Nfkd,
+}
+
+pub fn parse_normalization(form: &str) -> io::Result<Normalization> {
+    match form.trim().to_ascii_lowercase().as_str() {
+        "none" => Ok(Normalization::None),
+        "nfc" => Ok(Normalization::Nfc),
+        "nfd" => Ok(Normalization::Nfd),
+        "nfkc" => Ok(Normalization::Nfkc),
+        "nfkd" => Ok(Normalization::Nfkd),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unknown normalization '{}', use nfc, nfd, nfkc, nfkd or none", form),
+        )),
+    }
+}
+
+pub fn normalize(text: &str, form: Normalization) -> String {
+    match form {
+        Normalization::None => text.to_string(),
+        Normalization::Nfc => text.nfc().collect(),
+        Normalization::Nfd => text.nfd().collect(),
+        Normalization::Nfkc => text.nfkc().collect(),
+        Normalization::Nfkd => text.nfkd().collect(),
+    }
+}
+
+pub fn decompose_missing(file: &mut File, cmap_offset: u32, text: &str) -> io::Result<String> {
+    //Splits the text into grapheme clusters (UAX #29). A cluster with a character the font has no glyph for
+    //is decomposed (NFD) when the font has all of its parts, so a missing "ǹ" is drawn as "n" and a combining grave.
+    let graphemes: Vec<&str> = text.graphemes(true).collect();
+    println!("Debug: Grapheme clusters: {:?}", graphemes);
+
+    let mut result = String::with_capacity(text.len());
+    for grapheme in graphemes {
+        if !cmap::has_glyphs(file, cmap_offset, grapheme)? {
+            let decomposed: String = grapheme.nfd().collect();
+            if decomposed != grapheme && cmap::has_glyphs(file, cmap_offset, &decomposed)? {
+                println!("Debug: {:?} has no glyph, using its decomposition {:?}", grapheme, decomposed);
+                result.push_str(&decomposed);
+                continue;
+            }
+        }
+        result.push_str(grapheme);
+    }
+    Ok(result)
+}
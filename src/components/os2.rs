@@ -0,0 +1,46 @@
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom};
+use byteorder::{BigEndian, ReadBytesExt};
+
+//Vertical metrics from the OS/2 table. sxHeight and sCapHeight only exist from version 2 on.
+//fsSelection bit 7 (USE_TYPO_METRICS) asks for the typo metrics instead of hhea when spacing lines.
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Os2Metrics {
+    pub typo_ascender: i16,
+    pub typo_descender: i16,
+    pub typo_line_gap: i16,
+    pub x_height: Option<i16>,
+    pub cap_height: Option<i16>,
+    pub use_typo_metrics: bool,
+}
+
+pub fn parse_os2(file: &mut File, os2_offset: u32) -> io::Result<Os2Metrics> {
+    if os2_offset == 0 {
+        return Ok(Os2Metrics::default());
+    }
+
+    file.seek(SeekFrom::Start(os2_offset as u64))?;
+    let version = file.read_u16::<BigEndian>()?;
+
+    // fsSelection is at byte 62
+    file.seek(SeekFrom::Start(os2_offset as u64 + 62))?;
+    let fs_selection = file.read_u16::<BigEndian>()?;
+    let use_typo_metrics = fs_selection & 0x0080 != 0;
+
+    // sTypoAscender, sTypoDescender and sTypoLineGap are at byte 68
+    file.seek(SeekFrom::Start(os2_offset as u64 + 68))?;
+    let typo_ascender = file.read_i16::<BigEndian>()?;
+    let typo_descender = file.read_i16::<BigEndian>()?;
+    let typo_line_gap = file.read_i16::<BigEndian>()?;
+
+    // sxHeight and sCapHeight follow the code page ranges at byte 86
+    let (mut x_height, mut cap_height) = (None, None);
+    if version >= 2 {
+        file.seek(SeekFrom::Start(os2_offset as u64 + 86))?;
+        x_height = Some(file.read_i16::<BigEndian>()?);
+        cap_height = Some(file.read_i16::<BigEndian>()?);
+    }
+
+    Ok(Os2Metrics { typo_ascender, typo_descender, typo_line_gap, x_height, cap_height, use_typo_metrics })
+}
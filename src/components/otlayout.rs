@@ -0,0 +1,236 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use byteorder::{BigEndian, ReadBytesExt};
+use crate::components::gdef;
+
+//Common structures of the OpenType layout tables GSUB and GPOS:
+//ScriptList -> LangSys -> feature indices, FeatureList -> lookup indices, LookupList -> subtables,
+//and the Coverage and ClassDef tables the subtables use to match glyphs.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lookup {
+    pub lookup_type: u16,
+    pub lookup_flag: u16,
+    //Index of the GDEF mark glyph set whose marks the lookup doesn't skip, with UseMarkFilteringSet
+    pub mark_filtering_set: Option<u16>,
+    //Absolute file offsets of the subtables, extension subtables already resolved
+    pub subtables: Vec<u32>,
+}
+
+pub fn read_tag(file: &mut File) -> io::Result<String> {
+    let mut tag_bytes = [0; 4];
+    file.read_exact(&mut tag_bytes)?;
+    Ok(String::from_utf8_lossy(&tag_bytes).to_string())
+}
+
+pub fn has_script(file: &mut File, table_offset: u32, script_tag: &str) -> io::Result<bool> {
+    //Whether the ScriptList has the script, to choose between the old and new Indic script tags
+    file.seek(SeekFrom::Start(table_offset as u64 + 4))?;
+    let script_list_offset = table_offset + file.read_u16::<BigEndian>()? as u32;
+    file.seek(SeekFrom::Start(script_list_offset as u64))?;
+    let script_count = file.read_u16::<BigEndian>()?;
+    for _ in 0..script_count {
+        let tag = read_tag(file)?;
+        let _offset = file.read_u16::<BigEndian>()?;
+        if tag == script_tag {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+pub fn find_feature_lookups(file: &mut File, table_offset: u32, script_tag: &str, language_tag: Option<&str>, feature_tags: &[String]) -> io::Result<Vec<u16>> {
+    //Returns the lookup indices of the requested features in LookupList order,
+    //which is the order the lookups have to be applied in
+    file.seek(SeekFrom::Start(table_offset as u64))?;
+    let _major_version = file.read_u16::<BigEndian>()?;
+    let _minor_version = file.read_u16::<BigEndian>()?;
+    let script_list_offset = table_offset + file.read_u16::<BigEndian>()? as u32;
+    let feature_list_offset = table_offset + file.read_u16::<BigEndian>()? as u32;
+
+    // Script list: pick the requested script, fall back to the default script and then Latin.
+    // A font with none of them has no lookups for this text, another script's would be wrong.
+    file.seek(SeekFrom::Start(script_list_offset as u64))?;
+    let script_count = file.read_u16::<BigEndian>()?;
+    let mut scripts = Vec::new();
+    for _ in 0..script_count {
+        let tag = read_tag(file)?;
+        let offset = file.read_u16::<BigEndian>()?;
+        scripts.push((tag, offset));
+    }
+    let script = [script_tag, "DFLT", "latn"]
+        .iter()
+        .find_map(|wanted| scripts.iter().find(|(tag, _)| tag == wanted));
+    let Some((_, script_offset)) = script else { return Ok(Vec::new()) };
+    let script_offset = script_list_offset + *script_offset as u32;
+
+    // Use the language system of the requested language, or the default one of the script
+    file.seek(SeekFrom::Start(script_offset as u64))?;
+    let default_lang_sys = file.read_u16::<BigEndian>()?;
+    let lang_sys_count = file.read_u16::<BigEndian>()?;
+    let mut lang_systems = Vec::new();
+    for _ in 0..lang_sys_count {
+        let tag = read_tag(file)?;
+        let offset = file.read_u16::<BigEndian>()?;
+        lang_systems.push((tag, offset));
+    }
+    let language = language_tag.and_then(|wanted| lang_systems.iter().find(|(tag, _)| tag == wanted));
+    let lang_sys_offset = if let Some((_, offset)) = language {
+        script_offset + *offset as u32
+    } else if default_lang_sys != 0 {
+        script_offset + default_lang_sys as u32
+    } else if let Some((_, offset)) = lang_systems.first() {
+        script_offset + *offset as u32
+    } else {
+        return Ok(Vec::new());
+    };
+
+    // LangSys: lookupOrder (reserved), requiredFeatureIndex, featureIndexCount, featureIndices
+    file.seek(SeekFrom::Start(lang_sys_offset as u64))?;
+    let _lookup_order = file.read_u16::<BigEndian>()?;
+    let required_feature = file.read_u16::<BigEndian>()?;
+    let feature_index_count = file.read_u16::<BigEndian>()?;
+    let mut feature_indices = Vec::new();
+    for _ in 0..feature_index_count {
+        feature_indices.push(file.read_u16::<BigEndian>()?);
+    }
+    if required_feature != 0xFFFF {
+        feature_indices.push(required_feature);
+    }
+
+    // Feature list: collect the lookups of the features with a requested tag
+    let mut lookup_indices = Vec::new();
+    for feature_index in feature_indices {
+        file.seek(SeekFrom::Start(feature_list_offset as u64 + 2 + feature_index as u64 * 6))?;
+        let tag = read_tag(file)?;
+        let feature_offset = feature_list_offset + file.read_u16::<BigEndian>()? as u32;
+        if !feature_tags.contains(&tag) {
+            continue;
+        }
+
+        file.seek(SeekFrom::Start(feature_offset as u64))?;
+        let _feature_params = file.read_u16::<BigEndian>()?;
+        let lookup_index_count = file.read_u16::<BigEndian>()?;
+        for _ in 0..lookup_index_count {
+            lookup_indices.push(file.read_u16::<BigEndian>()?);
+        }
+    }
+
+    lookup_indices.sort_unstable();
+    lookup_indices.dedup();
+    Ok(lookup_indices)
+}
+
+pub fn read_lookup(file: &mut File, table_offset: u32, lookup_index: u16, extension_type: u16) -> io::Result<Lookup> {
+    //extension_type is the lookup type that wraps subtables with 32 bit offsets (7 in GSUB, 9 in GPOS)
+    file.seek(SeekFrom::Start(table_offset as u64 + 8))?;
+    let lookup_list_offset = table_offset + file.read_u16::<BigEndian>()? as u32;
+
+    file.seek(SeekFrom::Start(lookup_list_offset as u64 + 2 + lookup_index as u64 * 2))?;
+    let lookup_offset = lookup_list_offset + file.read_u16::<BigEndian>()? as u32;
+
+    file.seek(SeekFrom::Start(lookup_offset as u64))?;
+    let mut lookup_type = file.read_u16::<BigEndian>()?;
+    let lookup_flag = file.read_u16::<BigEndian>()?;
+    let subtable_count = file.read_u16::<BigEndian>()?;
+    let mut subtables = Vec::new();
+    for _ in 0..subtable_count {
+        subtables.push(lookup_offset + file.read_u16::<BigEndian>()? as u32);
+    }
+    let mark_filtering_set = if lookup_flag & gdef::USE_MARK_FILTERING_SET != 0 {
+        Some(file.read_u16::<BigEndian>()?)
+    } else {
+        None
+    };
+
+    if lookup_type == extension_type {
+        // Extension subtable: format, extensionLookupType, extensionOffset (32 bit)
+        for subtable in subtables.iter_mut() {
+            file.seek(SeekFrom::Start(*subtable as u64 + 2))?;
+            lookup_type = file.read_u16::<BigEndian>()?;
+            *subtable += file.read_u32::<BigEndian>()?;
+        }
+    }
+
+    Ok(Lookup { lookup_type, lookup_flag, mark_filtering_set, subtables })
+}
+
+pub fn coverage_index(file: &mut File, coverage_offset: u32, glyph: u32) -> io::Result<Option<u16>> {
+    //Coverage format 1 lists glyphs, format 2 lists ranges of glyphs with the coverage index of the range start
+    file.seek(SeekFrom::Start(coverage_offset as u64))?;
+    let format = file.read_u16::<BigEndian>()?;
+    let count = file.read_u16::<BigEndian>()?;
+
+    match format {
+        1 => {
+            for index in 0..count {
+                if file.read_u16::<BigEndian>()? as u32 == glyph {
+                    return Ok(Some(index));
+                }
+            }
+        }
+        2 => {
+            for _ in 0..count {
+                let start = file.read_u16::<BigEndian>()? as u32;
+                let end = file.read_u16::<BigEndian>()? as u32;
+                let start_index = file.read_u16::<BigEndian>()?;
+                if glyph >= start && glyph <= end {
+                    return Ok(Some(start_index + (glyph - start) as u16));
+                }
+            }
+        }
+        _ => {
+            println!("Debug: Unsupported coverage format: {}", format);
+        }
+    }
+
+    Ok(None)
+}
+
+pub fn glyph_class(file: &mut File, class_def_offset: u32, glyph: u32) -> io::Result<u16> {
+    //ClassDef format 1 is an array of classes from a start glyph, format 2 lists class ranges.
+    //Glyphs not listed are class 0.
+    file.seek(SeekFrom::Start(class_def_offset as u64))?;
+    let format = file.read_u16::<BigEndian>()?;
+
+    match format {
+        1 => {
+            let start_glyph = file.read_u16::<BigEndian>()? as u32;
+            let glyph_count = file.read_u16::<BigEndian>()? as u32;
+            if glyph >= start_glyph && glyph < start_glyph + glyph_count {
+                file.seek(SeekFrom::Current(((glyph - start_glyph) * 2) as i64))?;
+                return file.read_u16::<BigEndian>();
+            }
+        }
+        2 => {
+            let range_count = file.read_u16::<BigEndian>()?;
+            for _ in 0..range_count {
+                let start = file.read_u16::<BigEndian>()? as u32;
+                let end = file.read_u16::<BigEndian>()? as u32;
+                let class = file.read_u16::<BigEndian>()?;
+                if glyph >= start && glyph <= end {
+                    return Ok(class);
+                }
+            }
+        }
+        _ => {
+            println!("Debug: Unsupported class definition format: {}", format);
+        }
+    }
+
+    Ok(0)
+}
+
+pub fn read_u16_at(file: &mut File, offset: u32) -> io::Result<u16> {
+    file.seek(SeekFrom::Start(offset as u64))?;
+    file.read_u16::<BigEndian>()
+}
+
+pub fn read_u16_array(file: &mut File, count: u16) -> io::Result<Vec<u16>> {
+    //Reads count values from the current position
+    let mut values = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        values.push(file.read_u16::<BigEndian>()?);
+    }
+    Ok(values)
+}
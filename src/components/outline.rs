@@ -0,0 +1,94 @@
+//A glyph outline is a list of contours, each contour a closed list of points.
+//TrueType outlines mix on-curve points with off-curve quadratic control points, and two
+//consecutive off-curve points imply an on-curve point halfway between them.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+    pub on_curve: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Segment {
+    Line((f32, f32), (f32, f32)),
+    Quad((f32, f32), (f32, f32), (f32, f32)),
+    //CFF outlines are made of cubic curves, TrueType outlines never produce them
+    #[allow(dead_code)]
+    Cubic((f32, f32), (f32, f32), (f32, f32), (f32, f32)),
+}
+
+impl Segment {
+    pub fn start(&self) -> (f32, f32) {
+        match *self {
+            Segment::Line(p0, _) => p0,
+            Segment::Quad(p0, _, _) => p0,
+            Segment::Cubic(p0, _, _, _) => p0,
+        }
+    }
+
+    //Apply an affine map to every point of the segment
+    pub fn map(&self, f: impl Fn((f32, f32)) -> (f32, f32)) -> Segment {
+        match *self {
+            Segment::Line(p0, p1) => Segment::Line(f(p0), f(p1)),
+            Segment::Quad(p0, p1, p2) => Segment::Quad(f(p0), f(p1), f(p2)),
+            Segment::Cubic(p0, p1, p2, p3) => Segment::Cubic(f(p0), f(p1), f(p2), f(p3)),
+        }
+    }
+}
+
+fn midpoint(a: &Point, b: &Point) -> (f32, f32) {
+    ((a.x + b.x) / 2.0, (a.y + b.y) / 2.0)
+}
+
+pub fn contour_segments(contour: &[Point]) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    if contour.is_empty() {
+        return segments;
+    }
+
+    //Find an on-curve point to start from, if the contour has none start at the
+    //implied point between the last and the first off-curve point
+    let n = contour.len();
+    let start_index = contour.iter().position(|p| p.on_curve);
+    let (start, first) = match start_index {
+        Some(i) => ((contour[i].x, contour[i].y), i + 1),
+        None => (midpoint(&contour[n - 1], &contour[0]), 0),
+    };
+
+    let mut current = start;
+    let mut control: Option<(f32, f32)> = None;
+    for k in 0..n {
+        let point = &contour[(first + k) % n];
+        if point.on_curve {
+            let to = (point.x, point.y);
+            match control.take() {
+                Some(c) => segments.push(Segment::Quad(current, c, to)),
+                None => segments.push(Segment::Line(current, to)),
+            }
+            current = to;
+        } else if let Some(c) = control {
+            //Two off-curve points in a row imply an on-curve point between them
+            let to = ((c.0 + point.x) / 2.0, (c.1 + point.y) / 2.0);
+            segments.push(Segment::Quad(current, c, to));
+            current = to;
+            control = Some((point.x, point.y));
+        } else {
+            control = Some((point.x, point.y));
+        }
+    }
+
+    //Close the contour back to the start point
+    match control {
+        Some(c) => segments.push(Segment::Quad(current, c, start)),
+        None => {
+            if current != start {
+                segments.push(Segment::Line(current, start));
+            }
+        }
+    }
+
+    //Drop the zero length line produced when the start point is also the last point
+    segments.retain(|s| !matches!(s, Segment::Line(a, b) if a == b));
+    segments
+}
@@ -0,0 +1,119 @@
+use crate::components::flatten;
+use crate::components::outline::Segment;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct GlyphMeasure {
+    pub perimeter: f32,
+    pub area: f32,
+}
+
+pub fn measure_glyph(contours: &[Vec<Segment>], tolerance: f32) -> GlyphMeasure {
+    //Perimeter is the length of every contour, the path a cutter follows.
+    //Area is what the glyph fills with the non-zero winding rule the rasterizers use: holes (contours
+    //running the other way) are left out, and contours overlapping each other, as in many variable font
+    //glyphs, count once.
+    let mut perimeter = 0.0;
+    let mut polygons = Vec::with_capacity(contours.len());
+    for contour in contours {
+        let vertices = flatten::flatten_contour(contour, tolerance);
+        for (i, &(x0, y0)) in vertices.iter().enumerate() {
+            let (x1, y1) = vertices[(i + 1) % vertices.len()];
+            perimeter += ((x1 - x0) * (x1 - x0) + (y1 - y0) * (y1 - y0)).sqrt();
+        }
+        polygons.push(vertices);
+    }
+
+    GlyphMeasure { perimeter, area: nonzero_area(&polygons) }
+}
+
+//An edge of a flattened contour from its lower to its upper end, with +1 for upwards and -1 for downwards
+type Edge = ((f32, f32), (f32, f32), i32);
+
+fn nonzero_area(polygons: &[Vec<(f32, f32)>]) -> f32 {
+    //Splits the glyph into horizontal slabs at every vertex and every crossing of two edges. Inside a slab
+    //the edges don't cross, so the filled parts are trapezoids and the width at the middle of the slab is exact.
+    let mut edges: Vec<Edge> = Vec::new();
+    for vertices in polygons {
+        for (i, &p0) in vertices.iter().enumerate() {
+            let p1 = vertices[(i + 1) % vertices.len()];
+            if p0.1 < p1.1 {
+                edges.push((p0, p1, 1));
+            } else if p0.1 > p1.1 {
+                edges.push((p1, p0, -1));
+            }
+        }
+    }
+
+    let mut ys: Vec<f32> = edges.iter().flat_map(|&(low, high, _)| [low.1, high.1]).collect();
+    for (i, a) in edges.iter().enumerate() {
+        for b in &edges[i + 1..] {
+            if let Some(y) = crossing_y(a, b) {
+                ys.push(y);
+            }
+        }
+    }
+    ys.sort_by(f32::total_cmp);
+    ys.dedup();
+
+    let mut area = 0.0;
+    let mut crossings: Vec<(f32, i32)> = Vec::new();
+    for slab in ys.windows(2) {
+        let (y0, y1) = (slab[0], slab[1]);
+        let y = (y0 + y1) / 2.0;
+        crossings.clear();
+        for &(low, high, winding) in &edges {
+            if low.1 <= y && y < high.1 {
+                crossings.push((low.0 + (y - low.1) * (high.0 - low.0) / (high.1 - low.1), winding));
+            }
+        }
+        crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut winding = 0;
+        let mut width = 0.0;
+        for pair in crossings.windows(2) {
+            winding += pair[0].1;
+            if winding != 0 {
+                width += pair[1].0 - pair[0].0;
+            }
+        }
+        area += width * (y1 - y0);
+    }
+    area
+}
+
+fn crossing_y(a: &Edge, b: &Edge) -> Option<f32> {
+    //The height where two edges cross, if they do strictly inside both
+    let ((ax0, ay0), (ax1, ay1), _) = *a;
+    let ((bx0, by0), (bx1, by1), _) = *b;
+    let (adx, ady, bdx, bdy) = (ax1 - ax0, ay1 - ay0, bx1 - bx0, by1 - by0);
+    let denominator = adx * bdy - ady * bdx;
+    if denominator == 0.0 {
+        return None;
+    }
+    let t = ((bx0 - ax0) * bdy - (by0 - ay0) * bdx) / denominator;
+    let u = ((bx0 - ax0) * ady - (by0 - ay0) * adx) / denominator;
+    if t > 0.0 && t < 1.0 && u > 0.0 && u < 1.0 {
+        Some(ay0 + t * ady)
+    } else {
+        None
+    }
+}
+
+pub fn print_report(input_string: &str, glyph_indices: &[u32], clusters: &[usize], glyphs: &[Vec<Vec<Segment>>], tolerance: f32) {
+    //Every glyph is labelled with the characters of its cluster, a ligature shows all of its characters
+    let chars: Vec<char> = input_string.chars().collect();
+    let mut total = GlyphMeasure::default();
+
+    println!("{:<8} {:>8} {:>14} {:>14}", "Text", "Glyph", "Perimeter mm", "Area mm2");
+    for (i, (glyph_index, contours)) in glyph_indices.iter().zip(glyphs).enumerate() {
+        let measure = measure_glyph(contours, tolerance);
+        let start = clusters.get(i).copied().unwrap_or(i);
+        // The cluster ends where the next cluster in the text starts, glyphs of right-to-left runs come in reverse
+        let end = clusters.iter().filter(|&&c| c > start).min().copied().unwrap_or(chars.len());
+        // The line break after the last glyph of a line has no glyph of its own
+        let text: String = chars.get(start..end.max(start)).unwrap_or_default().iter().filter(|&&c| !matches!(c, '\n' | '\r' | '\u{2028}')).collect();
+        println!("{:<8} {:>8} {:>14.4} {:>14.4}", format!("{:?}", text), glyph_index, measure.perimeter, measure.area);
+        total.perimeter += measure.perimeter;
+        total.area += measure.area;
+    }
+    println!("{:<8} {:>8} {:>14.4} {:>14.4}", "Total", "", total.perimeter, total.area);
+}
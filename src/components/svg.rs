@@ -0,0 +1,65 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use crate::components::outline::Segment;
+use crate::components::flatten;
+
+//Flattening tolerance in mm for finding the extent of the outlines
+const BOUNDS_TOLERANCE: f32 = 0.01;
+
+pub fn write_svg(path: &str, glyphs: &[Vec<Vec<Segment>>], width_mm: f32, ascent_mm: f32, descent_mm: f32) -> io::Result<()> {
+    //The glyph outlines are expected in mm with the baseline at y = 0 and y pointing up.
+    //SVG has y pointing down, so everything is flipped around the top of the box.
+    //width/height carry the mm unit and the viewBox uses the same numbers, so one user unit is one mm.
+    //The box spans the advances and the ascender to descender, grown to the outlines where they reach past it,
+    //like the left side bearing of an "A" that starts slightly before the pen position.
+    let (mut left, mut right, mut bottom, mut top) = (0.0f32, width_mm, descent_mm, ascent_mm);
+    for contour in glyphs.iter().flatten() {
+        for (x, y) in flatten::flatten_contour(contour, BOUNDS_TOLERANCE) {
+            left = left.min(x);
+            right = right.max(x);
+            bottom = bottom.min(y);
+            top = top.max(y);
+        }
+    }
+    let (box_width, box_height) = (right - left, top - bottom);
+    let flip = |(x, y): (f32, f32)| (x, top - y);
+
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>"#)?;
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" version="1.1" width="{:.4}mm" height="{:.4}mm" viewBox="{:.4} 0 {:.4} {:.4}">"#,
+        box_width, box_height, left, box_width, box_height
+    )?;
+
+    for contours in glyphs {
+        if contours.is_empty() {
+            continue;
+        }
+        let mut d = String::new();
+        for contour in contours {
+            let Some(first) = contour.first() else { continue };
+            let (x, y) = flip(first.start());
+            d.push_str(&format!("M{:.4} {:.4}", x, y));
+            for segment in contour {
+                match segment.map(flip) {
+                    Segment::Line(_, (x, y)) => d.push_str(&format!(" L{:.4} {:.4}", x, y)),
+                    Segment::Quad(_, (cx, cy), (x, y)) => {
+                        d.push_str(&format!(" Q{:.4} {:.4} {:.4} {:.4}", cx, cy, x, y))
+                    }
+                    Segment::Cubic(_, (c1x, c1y), (c2x, c2y), (x, y)) => {
+                        d.push_str(&format!(" C{:.4} {:.4} {:.4} {:.4} {:.4} {:.4}", c1x, c1y, c2x, c2y, x, y))
+                    }
+                }
+            }
+            d.push_str(" Z ");
+        }
+        writeln!(out, r#"  <path d="{}" fill="black" fill-rule="nonzero"/>"#, d.trim_end())?;
+    }
+
+    writeln!(out, "</svg>")?;
+    out.flush()?;
+
+    println!("SVG written to {}", path);
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use crate::components::flatten;
+use crate::components::outline::Segment;
+
+//HPGL plotter units, 1 unit = 0.025mm
+const HPGL_UNITS_PER_MM: f32 = 40.0;
+
+pub struct GcodeSettings {
+    pub feed: f32,
+    pub plunge_feed: f32,
+    pub safe_z: f32,
+    pub cut_depth: f32,
+}
+
+pub fn write_hpgl(path: &str, glyphs: &[Vec<Vec<Segment>>], tolerance: f32) -> io::Result<()> {
+    //Every contour is drawn as pen up to its start, pen down through all vertices and back to the start
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "IN;SP1;")?;
+
+    let to_units = |(x, y): (f32, f32)| ((x * HPGL_UNITS_PER_MM).round() as i32, (y * HPGL_UNITS_PER_MM).round() as i32);
+
+    let mut contour_count = 0;
+    for contours in glyphs {
+        for contour in contours {
+            let vertices = flatten::flatten_contour(contour, tolerance);
+            let Some(&start) = vertices.first() else { continue };
+
+            let (x, y) = to_units(start);
+            writeln!(out, "PU{},{};", x, y)?;
+            let mut pen_down = String::from("PD");
+            for &vertex in vertices.iter().skip(1).chain(std::iter::once(&start)) {
+                let (x, y) = to_units(vertex);
+                pen_down.push_str(&format!("{},{},", x, y));
+            }
+            pen_down.pop();
+            writeln!(out, "{};", pen_down)?;
+            contour_count += 1;
+        }
+    }
+
+    writeln!(out, "PU;SP0;")?;
+    out.flush()?;
+
+    println!("HPGL written to {} ({} contours)", path, contour_count);
+    Ok(())
+}
+
+pub fn write_gcode(path: &str, glyphs: &[Vec<Vec<Segment>>], tolerance: f32, settings: &GcodeSettings) -> io::Result<()> {
+    //Absolute mm coordinates, Z = 0 is the work surface and the cut goes down to -cut_depth.
+    //Each contour: rapid to the start at safe Z, plunge, cut around and back to the start, retract.
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "(Text outline toolpath, units mm)")?;
+    writeln!(out, "G21")?;
+    writeln!(out, "G90")?;
+    writeln!(out, "G0 Z{:.3}", settings.safe_z)?;
+
+    let mut contour_count = 0;
+    for contours in glyphs {
+        for contour in contours {
+            let vertices = flatten::flatten_contour(contour, tolerance);
+            let Some(&(start_x, start_y)) = vertices.first() else { continue };
+
+            writeln!(out, "G0 X{:.4} Y{:.4}", start_x, start_y)?;
+            writeln!(out, "G1 Z{:.3} F{:.1}", -settings.cut_depth, settings.plunge_feed)?;
+            let mut first_move = true;
+            for &(x, y) in vertices.iter().skip(1).chain(std::iter::once(&(start_x, start_y))) {
+                if first_move {
+                    writeln!(out, "G1 X{:.4} Y{:.4} F{:.1}", x, y, settings.feed)?;
+                    first_move = false;
+                } else {
+                    writeln!(out, "G1 X{:.4} Y{:.4}", x, y)?;
+                }
+            }
+            writeln!(out, "G0 Z{:.3}", settings.safe_z)?;
+            contour_count += 1;
+        }
+    }
+
+    writeln!(out, "M2")?;
+    out.flush()?;
+
+    println!("G-code written to {} ({} contours)", path, contour_count);
+    Ok(())
+}
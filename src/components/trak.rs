@@ -0,0 +1,66 @@
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom};
+use byteorder::{BigEndian, ReadBytesExt};
+
+pub fn get_tracking(file: &mut File, trak_offset: u32, point_size: f32) -> io::Result<i32> {
+    //The tracking table holds letter spacing that depends on the point size,
+    //tight at display sizes and loose at caption sizes.
+    //Returns the value of the normal (0.0) track in font units, added to every advance.
+
+    // Header: version (Fixed), format, horizOffset, vertOffset, reserved
+    file.seek(SeekFrom::Start(trak_offset as u64 + 6))?;
+    let horiz_offset = file.read_u16::<BigEndian>()?;
+    if horiz_offset == 0 {
+        return Ok(0);
+    }
+
+    // TrackData: nTracks, nSizes, sizeTableOffset (32, from the start of trak), then the track entries
+    let track_data = trak_offset as u64 + horiz_offset as u64;
+    file.seek(SeekFrom::Start(track_data))?;
+    let n_tracks = file.read_u16::<BigEndian>()?;
+    let n_sizes = file.read_u16::<BigEndian>()?;
+    let size_table = trak_offset as u64 + file.read_u32::<BigEndian>()? as u64;
+
+    // Track entries: track (Fixed), nameIndex, offset of the per size values
+    let mut values_offset = None;
+    for _ in 0..n_tracks {
+        let track = file.read_i32::<BigEndian>()?;
+        let _name_index = file.read_u16::<BigEndian>()?;
+        let offset = file.read_u16::<BigEndian>()?;
+        if track == 0 {
+            values_offset = Some(trak_offset as u64 + offset as u64);
+        }
+    }
+    let Some(values_offset) = values_offset else { return Ok(0) };
+    if n_sizes == 0 {
+        return Ok(0);
+    }
+
+    file.seek(SeekFrom::Start(size_table))?;
+    let mut sizes = Vec::new();
+    for _ in 0..n_sizes {
+        sizes.push(file.read_i32::<BigEndian>()? as f32 / 65536.0);
+    }
+    file.seek(SeekFrom::Start(values_offset))?;
+    let mut values = Vec::new();
+    for _ in 0..n_sizes {
+        values.push(file.read_i16::<BigEndian>()? as f32);
+    }
+
+    // Interpolate linearly between the two sizes around the point size, extrapolate outside the table
+    let tracking = if sizes.len() == 1 {
+        values[0]
+    } else {
+        let upper = sizes.iter().position(|&s| s >= point_size).unwrap_or(sizes.len() - 1).max(1);
+        let lower = upper - 1;
+        let span = sizes[upper] - sizes[lower];
+        if span == 0.0 {
+            values[lower]
+        } else {
+            values[lower] + (values[upper] - values[lower]) * (point_size - sizes[lower]) / span
+        }
+    };
+
+    println!("Debug: trak tracking at {:.2}pt is {:.1} font units", point_size, tracking);
+    Ok(tracking.round() as i32)
+}
@@ -0,0 +1,259 @@
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom};
+use byteorder::{BigEndian, ReadBytesExt};
+use crate::components::features;
+use crate::components::fvar::{self, VariationAxis};
+
+//Shared parts of OpenType font variations: choosing an instance from user axis values,
+//normalizing them (with avar) and reading deltas from an ItemVariationStore.
+
+//An instance of a variable font in normalized coordinates
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Instance {
+    //One coordinate in -1..1 per fvar axis, empty for the default instance or a static font
+    pub coords: Vec<f32>,
+    //Absolute offset of the GDEF ItemVariationStore that GPOS device tables refer to, 0 when absent
+    pub gdef_var_store: u32,
+}
+
+impl Instance {
+    pub fn is_default(&self) -> bool {
+        self.coords.iter().all(|&c| c == 0.0)
+    }
+}
+
+pub fn parse_axis_settings(axes: &str) -> io::Result<Vec<(String, f32)>> {
+    //Axis values given like "wght=650,wdth=90", in the user units of fvar
+    let mut settings = Vec::new();
+    for item in axes.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+        let Some((tag, value)) = item.split_once('=') else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Axis setting '{}' needs a value, like wght=650", item)));
+        };
+        let value: f32 = value.trim().parse().map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid axis value in '{}'", item))
+        })?;
+        settings.push((features::to_tag(tag.trim())?, value));
+    }
+    Ok(settings)
+}
+
+pub fn normalize(axes: &[VariationAxis], settings: &[(String, f32)]) -> io::Result<Vec<f32>> {
+    //Maps the user value of every axis to -1..0..1 around its default, axes not given stay at default
+    for (tag, _) in settings {
+        if !axes.iter().any(|axis| &axis.tag == tag) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("The font has no '{}' axis", tag)));
+        }
+    }
+
+    let coords = axes
+        .iter()
+        .map(|axis| {
+            let value = settings
+                .iter()
+                .rev()
+                .find(|(tag, _)| tag == &axis.tag)
+                .map(|&(_, value)| value)
+                .unwrap_or(axis.default_value)
+                .clamp(axis.min_value, axis.max_value);
+            if value < axis.default_value && axis.default_value > axis.min_value {
+                (value - axis.default_value) / (axis.default_value - axis.min_value)
+            } else if value > axis.default_value && axis.max_value > axis.default_value {
+                (value - axis.default_value) / (axis.max_value - axis.default_value)
+            } else {
+                0.0
+            }
+        })
+        .collect();
+    Ok(coords)
+}
+
+pub fn apply_avar(file: &mut File, avar_offset: u32, coords: &mut [f32]) -> io::Result<()> {
+    //The axis variations table bends the default normalization with a piecewise linear map per axis
+    // Header: majorVersion, minorVersion, reserved, axisCount, then a SegmentMaps record per axis
+    file.seek(SeekFrom::Start(avar_offset as u64 + 6))?;
+    let axis_count = file.read_u16::<BigEndian>()?;
+
+    for coord in coords.iter_mut().take(axis_count as usize) {
+        let position_map_count = file.read_u16::<BigEndian>()?;
+        let mut maps = Vec::new();
+        for _ in 0..position_map_count {
+            let from = read_f2dot14(file)?;
+            let to = read_f2dot14(file)?;
+            maps.push((from, to));
+        }
+        *coord = map_segments(&maps, *coord);
+    }
+
+    Ok(())
+}
+
+fn map_segments(maps: &[(f32, f32)], coord: f32) -> f32 {
+    //Maps need at least the -1, 0 and 1 entries to mean anything
+    if maps.len() < 3 {
+        return coord;
+    }
+    for pair in maps.windows(2) {
+        let (from0, to0) = pair[0];
+        let (from1, to1) = pair[1];
+        if coord == from0 {
+            return to0;
+        }
+        if coord > from0 && coord < from1 {
+            return to0 + (to1 - to0) * (coord - from0) / (from1 - from0);
+        }
+    }
+    maps.last().map(|&(_, to)| to).unwrap_or(coord)
+}
+
+pub fn to_f2dot14(coord: f32) -> f32 {
+    //Normalized coordinates are rounded to the F2Dot14 grid the tables are stored in
+    (coord * 16384.0).round() / 16384.0
+}
+
+pub fn read_f2dot14(file: &mut File) -> io::Result<f32> {
+    Ok(file.read_i16::<BigEndian>()? as f32 / 16384.0)
+}
+
+pub fn axis_scalar(coord: f32, start: f32, peak: f32, end: f32) -> f32 {
+    //How much of a region's delta applies on one axis: 1 at the peak, falling to 0 at start and end
+    if start > peak || peak > end || (start < 0.0 && end > 0.0 && peak != 0.0) || peak == 0.0 {
+        return 1.0;
+    }
+    if coord < start || coord > end {
+        return 0.0;
+    }
+    if coord == peak {
+        1.0
+    } else if coord < peak {
+        (coord - start) / (peak - start)
+    } else {
+        (end - coord) / (end - peak)
+    }
+}
+
+pub fn item_delta(file: &mut File, store_offset: u32, outer: u16, inner: u16, coords: &[f32]) -> io::Result<f32> {
+    //Reads one delta set of an ItemVariationStore and blends its region deltas for the instance
+    // Header: format, variationRegionListOffset (32), itemVariationDataCount, itemVariationDataOffsets (32)
+    file.seek(SeekFrom::Start(store_offset as u64 + 2))?;
+    let region_list = store_offset + file.read_u32::<BigEndian>()?;
+    let data_count = file.read_u16::<BigEndian>()?;
+    if outer >= data_count {
+        return Ok(0.0);
+    }
+    file.seek(SeekFrom::Start(store_offset as u64 + 8 + outer as u64 * 4))?;
+    let data = store_offset + file.read_u32::<BigEndian>()?;
+
+    // ItemVariationData: itemCount, wordDeltaCount, regionIndexCount, regionIndexes[]
+    // wordDeltaCount 0x8000 means the word deltas are 32 bit and the others 16 bit
+    file.seek(SeekFrom::Start(data as u64))?;
+    let item_count = file.read_u16::<BigEndian>()?;
+    let word_delta_count = file.read_u16::<BigEndian>()?;
+    let region_index_count = file.read_u16::<BigEndian>()?;
+    if inner >= item_count {
+        return Ok(0.0);
+    }
+    let mut region_indexes = Vec::new();
+    for _ in 0..region_index_count {
+        region_indexes.push(file.read_u16::<BigEndian>()?);
+    }
+
+    let long_words = word_delta_count & 0x8000 != 0;
+    let word_count = (word_delta_count & 0x7FFF) as u64;
+    let (word_size, short_size) = if long_words { (4, 2) } else { (2, 1) };
+    let row_size = word_count * word_size + (region_index_count as u64 - word_count.min(region_index_count as u64)) * short_size;
+    file.seek(SeekFrom::Start(data as u64 + 6 + region_index_count as u64 * 2 + inner as u64 * row_size))?;
+    let mut deltas = Vec::new();
+    for k in 0..region_index_count as u64 {
+        let delta = match (k < word_count, long_words) {
+            (true, true) => file.read_i32::<BigEndian>()?,
+            (true, false) => file.read_i16::<BigEndian>()? as i32,
+            (false, true) => file.read_i16::<BigEndian>()? as i32,
+            (false, false) => file.read_i8()? as i32,
+        };
+        deltas.push(delta);
+    }
+
+    // VariationRegionList: axisCount, regionCount, then start, peak, end per axis per region
+    file.seek(SeekFrom::Start(region_list as u64))?;
+    let axis_count = file.read_u16::<BigEndian>()?;
+    let region_count = file.read_u16::<BigEndian>()?;
+    let mut total = 0.0;
+    for (&region_index, &delta) in region_indexes.iter().zip(&deltas) {
+        if delta == 0 || region_index >= region_count {
+            continue;
+        }
+        file.seek(SeekFrom::Start(region_list as u64 + 4 + region_index as u64 * axis_count as u64 * 6))?;
+        let mut scalar = 1.0;
+        for axis in 0..axis_count as usize {
+            let start = read_f2dot14(file)?;
+            let peak = read_f2dot14(file)?;
+            let end = read_f2dot14(file)?;
+            scalar *= axis_scalar(coords.get(axis).copied().unwrap_or(0.0), start, peak, end);
+        }
+        total += scalar * delta as f32;
+    }
+
+    Ok(total)
+}
+
+pub fn delta_set_index(file: &mut File, map_offset: u32, index: u32) -> io::Result<(u16, u16)> {
+    //A DeltaSetIndexMap turns a glyph id into the outer and inner index of its delta set,
+    //indices past the end of the map use the last entry
+    // Header: format (8), entryFormat (8), mapCount (16 for format 0, 32 for format 1)
+    file.seek(SeekFrom::Start(map_offset as u64))?;
+    let format = file.read_u8()?;
+    let entry_format = file.read_u8()?;
+    let (map_count, header_size) = if format == 0 {
+        (file.read_u16::<BigEndian>()? as u32, 4)
+    } else {
+        (file.read_u32::<BigEndian>()?, 6)
+    };
+    if map_count == 0 {
+        return Ok((0, index as u16));
+    }
+
+    let entry_size = (((entry_format & 0x30) >> 4) + 1) as u64;
+    let inner_bits = ((entry_format & 0x0F) + 1) as u32;
+    file.seek(SeekFrom::Start(map_offset as u64 + header_size + index.min(map_count - 1) as u64 * entry_size))?;
+    let mut entry: u32 = 0;
+    for _ in 0..entry_size {
+        entry = (entry << 8) | file.read_u8()? as u32;
+    }
+    Ok(((entry >> inner_bits) as u16, (entry & ((1 << inner_bits) - 1)) as u16))
+}
+
+pub fn read_instance(file: &mut File, fvar_offset: u32, avar_offset: u32, gdef_offset: u32, settings: &[(String, f32)]) -> io::Result<Instance> {
+    //Static fonts only have the default instance
+    if fvar_offset == 0 {
+        if !settings.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Axis values given, but the font is not a variable font"));
+        }
+        return Ok(Instance::default());
+    }
+
+    let axes = fvar::parse_fvar(file, fvar_offset)?;
+    let mut coords = normalize(&axes, settings)?;
+    if avar_offset != 0 {
+        apply_avar(file, avar_offset, &mut coords)?;
+    }
+    let coords: Vec<f32> = coords.into_iter().map(to_f2dot14).collect();
+    for (axis, coord) in axes.iter().zip(&coords) {
+        println!("Debug: Axis {} normalized to {}", axis.tag, coord);
+    }
+
+    // GDEF 1.3 has the ItemVariationStore offset (32 bit) after markGlyphSetsDefOffset
+    let mut gdef_var_store = 0;
+    if gdef_offset != 0 {
+        file.seek(SeekFrom::Start(gdef_offset as u64 + 2))?;
+        let minor_version = file.read_u16::<BigEndian>()?;
+        if minor_version >= 3 {
+            file.seek(SeekFrom::Start(gdef_offset as u64 + 14))?;
+            let store_offset = file.read_u32::<BigEndian>()?;
+            if store_offset != 0 {
+                gdef_var_store = gdef_offset + store_offset;
+            }
+        }
+    }
+
+    Ok(Instance { coords, gdef_var_store })
+}
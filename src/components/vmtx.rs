@@ -0,0 +1,51 @@
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom};
+use byteorder::{BigEndian, ReadBytesExt};
+
+//Vertical metrics: the vertical header (vhea) and the advance heights and top side bearings (vmtx),
+//the counterparts of hhea and hmtx for text set in columns.
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct VerticalMetric {
+    pub advance_height: u16,
+    pub top_side_bearing: i16,
+}
+
+pub fn parse_vhea(file: &mut File, vhea_offset: u32) -> io::Result<u16> {
+    // numOfLongVerMetrics is the last field of the 36 byte table, like numberOfHMetrics in hhea
+    file.seek(SeekFrom::Start(vhea_offset as u64 + 34))?;
+    file.read_u16::<BigEndian>()
+}
+
+pub fn parse_vmtx(file: &mut File, glyph_indices: &[u32], num_v_metrics: u16, vmtx_offset: u32) -> io::Result<Vec<VerticalMetric>> {
+    //Long metrics (advanceHeight, topSideBearing) for the first numOfLongVerMetrics glyphs,
+    //the glyphs after them repeat the last advance height and only store a top side bearing
+    if num_v_metrics == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "The vhea table has no vertical metrics"));
+    }
+
+    let mut metrics = Vec::new();
+    for &glyph_index in glyph_indices {
+        let metric = if glyph_index < num_v_metrics as u32 {
+            file.seek(SeekFrom::Start(vmtx_offset as u64 + glyph_index as u64 * 4))?;
+            let advance_height = file.read_u16::<BigEndian>()?;
+            let top_side_bearing = file.read_i16::<BigEndian>()?;
+            VerticalMetric { advance_height, top_side_bearing }
+        } else {
+            file.seek(SeekFrom::Start(vmtx_offset as u64 + (num_v_metrics as u64 - 1) * 4))?;
+            let advance_height = file.read_u16::<BigEndian>()?;
+            let extra = (glyph_index - num_v_metrics as u32) as u64;
+            file.seek(SeekFrom::Start(vmtx_offset as u64 + num_v_metrics as u64 * 4 + extra * 2))?;
+            let top_side_bearing = file.read_i16::<BigEndian>()?;
+            VerticalMetric { advance_height, top_side_bearing }
+        };
+
+        println!(
+            "Glyph Index: {}, Advance Height: {}",
+            glyph_index, metric.advance_height
+        );
+        metrics.push(metric);
+    }
+
+    Ok(metrics)
+}
@@ -0,0 +1,30 @@
+use std::fs::File;
+use std::io::{self, Seek, SeekFrom};
+use byteorder::{BigEndian, ReadBytesExt};
+
+//The vertical origin table of CFF fonts: the y coordinate of every glyph's vertical origin,
+//which TrueType fonts derive from the glyph's yMax and top side bearing instead.
+
+pub fn vertical_origin(file: &mut File, vorg_offset: u32, glyph_index: u32) -> io::Result<i16> {
+    // Header: majorVersion, minorVersion, defaultVertOriginY, numVertOriginYMetrics,
+    // then (glyphIndex, vertOriginY) records sorted by glyph
+    file.seek(SeekFrom::Start(vorg_offset as u64 + 4))?;
+    let default_origin = file.read_i16::<BigEndian>()?;
+    let count = file.read_u16::<BigEndian>()?;
+
+    let (mut low, mut high) = (0u32, count as u32);
+    while low < high {
+        let mid = (low + high) / 2;
+        file.seek(SeekFrom::Start(vorg_offset as u64 + 8 + mid as u64 * 4))?;
+        let glyph = file.read_u16::<BigEndian>()? as u32;
+        if glyph == glyph_index {
+            return file.read_i16::<BigEndian>();
+        } else if glyph < glyph_index {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+
+    Ok(default_origin)
+}
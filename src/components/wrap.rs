@@ -0,0 +1,82 @@
+use unicode_linebreak::{linebreaks, BreakOpportunity};
+use crate::components::layout::GlyphAdjustment;
+use crate::components::hyphenate::Patterns;
+
+//Wrapping text to a maximum width at the line break opportunities of the Unicode Line Breaking
+//Algorithm (UAX #14): after spaces, after hyphens, between ideographs, and so on.
+//The widths come from the shaped glyphs, so kerning and ligatures count.
+
+pub fn character_widths(char_count: usize, clusters: &[usize], advance_widths: &[u16], adjustments: &[GlyphAdjustment]) -> Vec<i32> {
+    //The width of every character in font units, the glyphs of a cluster count at its first character
+    let mut widths = vec![0; char_count];
+    for ((&cluster, &advance_width), adjustment) in clusters.iter().zip(advance_widths).zip(adjustments) {
+        if let Some(width) = widths.get_mut(cluster) {
+            *width += advance_width as i32 + adjustment.x_advance;
+        }
+    }
+    widths
+}
+
+pub struct Hyphenation<'a> {
+    pub patterns: &'a Patterns,
+    //Advance of the font's hyphen glyph in font units
+    pub hyphen_width: i32,
+}
+
+fn end_line(wrapped: &mut String) {
+    //Wraps the line, without the spaces it ends with
+    while wrapped.ends_with(|c: char| c.is_whitespace() && !matches!(c, '\n' | '\r' | '\u{2028}')) {
+        wrapped.pop();
+    }
+    wrapped.push('\n');
+}
+
+pub fn wrap_text(text: &str, widths: &[i32], max_width: i32, hyphenation: Option<&Hyphenation>) -> String {
+    //Fills every line with as many pieces between break opportunities as fit in max_width (font units),
+    //a piece wider than a line gets a line of its own. Spaces at the end of a line don't count towards
+    //its width and are left out where a line is wrapped. Existing line breaks are kept.
+    //With hyphenation a word that doesn't fit is broken at its last hyphenation point where the part
+    //before it and a hyphen still fit, instead of going to the next line as a whole.
+    let chars: Vec<char> = text.chars().collect();
+    let mut wrapped = String::with_capacity(text.len() + 8);
+    let mut line_width = 0;
+    let mut piece_start = 0;
+    for (byte_index, opportunity) in linebreaks(text) {
+        let piece_end = text[..byte_index].chars().count();
+        let mut start = piece_start;
+        let (visible, visible_width) = loop {
+            // The width up to the last character that isn't a space or a line break
+            let visible = chars[start..piece_end].iter().rposition(|c| !c.is_whitespace()).map_or(0, |i| i + 1);
+            let visible_width: i32 = widths[start..start + visible].iter().sum();
+            if line_width + visible_width <= max_width {
+                break (visible, visible_width);
+            }
+            let fitting_point = hyphenation.and_then(|hyphenation| {
+                hyphenation.patterns.hyphenation_points(&chars[start..start + visible]).into_iter().rev().find(|&point| {
+                    line_width + widths[start..start + point].iter().sum::<i32>() + hyphenation.hyphen_width <= max_width
+                })
+            });
+            if let Some(point) = fitting_point {
+                wrapped.extend(&chars[start..start + point]);
+                wrapped.push('-');
+                wrapped.push('\n');
+                start += point;
+            } else if line_width > 0 {
+                end_line(&mut wrapped);
+            } else {
+                break (visible, visible_width);
+            }
+            line_width = 0;
+        };
+        if visible_width > max_width {
+            println!("Debug: {:?} is wider than the maximum width and overflows its line", chars[start..start + visible].iter().collect::<String>());
+        }
+        wrapped.extend(&chars[start..piece_end]);
+        line_width += widths[start..piece_end].iter().sum::<i32>();
+        if opportunity == BreakOpportunity::Mandatory {
+            line_width = 0;
+        }
+        piece_start = piece_end;
+    }
+    wrapped
+}
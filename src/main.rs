@@ -1,7 +1,8 @@
 use std::fs::File;
 use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
 use byteorder::{BigEndian, ReadBytesExt};
-use crate::components::{kern, hmtx, cmap, glyf};
+use crate::components::{bidi, joining, indic, gdef, normalize, wrap, hyphenate, features, fvar, info, os2, mvar, vmtx, vorg, variations, hvar, gvar, kern, kerx, trak, morx, gpos, gsub, hmtx, cmap, glyf, layout, outline, svg, dxf, toolpath, report};
 use clap::{Parser, Subcommand};
 
 mod components;
@@ -18,15 +19,161 @@ enum Commands {
     Set {
         font: String,
         capital: f32,
-        text: String
+        text: String,
+        #[command(flatten)]
+        shaping: ShapingArgs,
+        #[command(flatten)]
+        variation: VariationArgs,
+        #[command(flatten)]
+        layout: LayoutArgs
+    },
+    /// Measure the text and write its outlines to an SVG file at true size in mm
+    Svg {
+        font: String,
+        capital: f32,
+        text: String,
+        #[command(flatten)]
+        shaping: ShapingArgs,
+        #[command(flatten)]
+        variation: VariationArgs,
+        #[command(flatten)]
+        layout: LayoutArgs,
+        output: String
+    },
+    /// Measure the text and write its contours to an R12 DXF file in mm
+    Dxf {
+        font: String,
+        capital: f32,
+        text: String,
+        #[command(flatten)]
+        shaping: ShapingArgs,
+        #[command(flatten)]
+        variation: VariationArgs,
+        #[command(flatten)]
+        layout: LayoutArgs,
+        output: String,
+        /// Layer the closed contours are written to
+        #[arg(long, default_value = "TEXT")]
+        layer: String,
+        /// Maximum distance in mm between a curve and its straight pieces
+        #[arg(long, default_value_t = 0.001)]
+        tolerance: f32
+    },
+    /// Measure the text and write its contours as an HPGL plot file
+    Hpgl {
+        font: String,
+        capital: f32,
+        text: String,
+        #[command(flatten)]
+        shaping: ShapingArgs,
+        #[command(flatten)]
+        variation: VariationArgs,
+        #[command(flatten)]
+        layout: LayoutArgs,
+        output: String,
+        /// Maximum distance in mm between a curve and its straight pieces
+        #[arg(long, default_value_t = 0.01)]
+        tolerance: f32
+    },
+    /// Measure the text and write an engraving toolpath as G-code
+    Gcode {
+        font: String,
+        capital: f32,
+        text: String,
+        #[command(flatten)]
+        shaping: ShapingArgs,
+        #[command(flatten)]
+        variation: VariationArgs,
+        #[command(flatten)]
+        layout: LayoutArgs,
+        output: String,
+        /// Maximum distance in mm between a curve and its straight pieces
+        #[arg(long, default_value_t = 0.01)]
+        tolerance: f32,
+        /// Cutting feed in mm/min
+        #[arg(long, default_value_t = 300.0)]
+        feed: f32,
+        /// Plunge feed in mm/min
+        #[arg(long, default_value_t = 100.0)]
+        plunge_feed: f32,
+        /// Height in mm for rapid moves above the work surface
+        #[arg(long, default_value_t = 5.0)]
+        safe_z: f32,
+        /// Depth in mm below the work surface to cut at
+        #[arg(long, default_value_t = 0.2)]
+        cut_depth: f32
+    },
+    /// Measure the text and report the contour length in mm and filled area in mm2 per glyph
+    Report {
+        font: String,
+        capital: f32,
+        text: String,
+        #[command(flatten)]
+        shaping: ShapingArgs,
+        #[command(flatten)]
+        variation: VariationArgs,
+        #[command(flatten)]
+        layout: LayoutArgs,
+        /// Maximum distance in mm between a curve and its straight pieces
+        #[arg(long, default_value_t = 0.001)]
+        tolerance: f32
+    },
+    /// Print the names of the font, and the axes and named instances of a variable font
+    Info {
+        font: String
     }
 }
 
+#[derive(clap::Args, Debug, Clone, Default)]
+struct ShapingArgs {
+    /// OpenType features to turn on or off, e.g. "liga=0,tnum,smcp,ss02,kern=0"
+    #[arg(long, default_value = "")]
+    features: String,
+    /// OpenType script tag used to select the GSUB/GPOS lookups
+    #[arg(long, default_value = "latn")]
+    script: String,
+    /// OpenType language system tag, e.g. "DEU" or "TRK", the script default is used when not given
+    #[arg(long)]
+    language: Option<String>,
+    /// Set the text in a vertical column and measure its height, with the vert/vrt2 alternates
+    #[arg(long)]
+    vertical: bool,
+    /// Unicode normalization of the text before the glyph lookup: nfc, nfd, nfkc, nfkd or none
+    #[arg(long, default_value = "nfc")]
+    normalization: String
+}
+
+#[derive(clap::Args, Debug, Clone, Default)]
+struct VariationArgs {
+    /// Axis values of a variable font in user units, e.g. "wght=650,wdth=90", other axes stay at their default
+    #[arg(long, default_value = "")]
+    axis: String,
+    /// Named instance of a variable font, e.g. "SemiBold Condensed", --axis values override its axes
+    #[arg(long)]
+    instance: Option<String>
+}
+
+#[derive(clap::Args, Debug, Clone, Default)]
+struct LayoutArgs {
+    /// Baseline to baseline distance in mm for text with several lines, instead of the font's line spacing
+    #[arg(long)]
+    leading: Option<f32>,
+    /// Wrap the text into lines no wider than this many mm, at the Unicode line break opportunities
+    #[arg(long)]
+    max_width: Option<f32>,
+    /// TeX hyphenation patterns (hyph-*.pat.txt) to hyphenate words that don't fit the --max-width
+    #[arg(long, requires = "max_width")]
+    hyphenate: Option<String>
+}
+
 #[derive(Default)]
 pub struct Variables {
     font_input: String,
     capital_input: f32,
-    text_input: String
+    text_input: String,
+    shaping_input: ShapingArgs,
+    variation_input: VariationArgs,
+    layout_input: LayoutArgs
 }
 
 #[derive(Debug, PartialEq)]
@@ -37,17 +184,69 @@ struct TableRecord {
     length: u32,
 }
 
+//Tables the text is shaped and measured with
+#[derive(Clone, Copy)]
+struct ShapingTables {
+    cmap_offset: u32,
+    glyf_offset: u32,
+    loca_offset: u32,
+    head_offset: u32,
+    hmtx_offset: u32,
+    maxp_offset: u32,
+    kern_offset: u32,
+    gpos_offset: u32,
+    gsub_offset: u32,
+    kerx_offset: u32,
+    ankr_offset: u32,
+    trak_offset: u32,
+    morx_offset: u32,
+    hvar_offset: u32,
+    gvar_offset: u32,
+    gdef_offset: u32,
+}
+
+//Tables the glyph outlines are read from
+struct OutlineSources {
+    glyf_offset: u32,
+    loca_offset: u32,
+    head_offset: u32,
+    gvar_offset: u32,
+}
+
+#[derive(Debug, Default)]
+struct HheaMetrics {
+    ascent: i16,
+    descent: i16,
+    line_gap: i16,
+    num_h_metrics: u16,
+}
+
 fn main() -> io::Result<()> {
     
     //Parse command line Args
     let mut input_data: Variables = Variables::default();
     let args = Args::parse();
 
-    match args.cmd {
-        Commands::Set{font, capital, text} => {
+    match args.cmd.clone() {
+        Commands::Set{font, capital, text, shaping, variation, layout}
+        | Commands::Svg{font, capital, text, shaping, variation, layout, ..}
+        | Commands::Dxf{font, capital, text, shaping, variation, layout, ..}
+        | Commands::Hpgl{font, capital, text, shaping, variation, layout, ..}
+        | Commands::Gcode{font, capital, text, shaping, variation, layout, ..}
+        | Commands::Report{font, capital, text, shaping, variation, layout, ..} => {
                 input_data.font_input = font;
                 input_data.capital_input = capital;
                 input_data.text_input = text;
+                input_data.shaping_input = shaping;
+                input_data.variation_input = variation;
+                input_data.layout_input = layout;
+        }
+        Commands::Info{font} => {
+            let mut file = File::open(font)?;
+            let tables = read_table_directory(&mut file)?;
+            let name_offset = find_table_offset("name", &tables)?;
+            let fvar_offset = find_table_offset("fvar", &tables)?;
+            return info::print_font_info(&mut file, name_offset, fvar_offset);
         }
     }
 
@@ -55,10 +254,408 @@ fn main() -> io::Result<()> {
     let ttf_path = input_data.font_input;
     let mut file = File::open(ttf_path)?;
     //Input string to measure
-    let input_string = input_data.text_input;
+    let mut input_string = input_data.text_input;
     //cap size  in mm
     let cap_size = input_data.capital_input;
+    //OpenType features, script and language used for GSUB and GPOS
+    let shaping = input_data.shaping_input;
+    let mut selection = features::FeatureSelection::parse(&shaping.features, &shaping.script, shaping.language.as_deref())?;
+    selection.vertical = shaping.vertical;
+    //Axis values of a variable font
+    let mut axis_settings = variations::parse_axis_settings(&input_data.variation_input.axis)?;
+
+    //Read the table directory with the offset and length of every table
+    let tables = read_table_directory(&mut file)?;
+
+    // Find necessary table offsets
+    let cmap_offset = find_table_offset("cmap", &tables)?;
+    let glyf_offset = find_table_offset("glyf", &tables)?;
+    let loca_offset = find_table_offset("loca", &tables)?;
+    let head_offset = find_table_offset("head", &tables)?; 
+    let hmtx_offset = find_table_offset("hmtx", &tables)?; 
+    let maxp_offset = find_table_offset("maxp", &tables)?; 
+    let hhea_offset = find_table_offset("hhea", &tables)?; 
+    let kern_offset = find_table_offset("kern", &tables)?; 
+    let gpos_offset = find_table_offset("GPOS", &tables)?; 
+    let gsub_offset = find_table_offset("GSUB", &tables)?; 
+    let kerx_offset = find_table_offset("kerx", &tables)?; 
+    let ankr_offset = find_table_offset("ankr", &tables)?; 
+    let trak_offset = find_table_offset("trak", &tables)?; 
+    let morx_offset = find_table_offset("morx", &tables)?; 
+    let fvar_offset = find_table_offset("fvar", &tables)?; 
+    let avar_offset = find_table_offset("avar", &tables)?; 
+    let hvar_offset = find_table_offset("HVAR", &tables)?; 
+    let gvar_offset = find_table_offset("gvar", &tables)?; 
+    let gdef_offset = find_table_offset("GDEF", &tables)?; 
+    let name_offset = find_table_offset("name", &tables)?; 
+    let os2_offset = find_table_offset("OS/2", &tables)?; 
+    let mvar_offset = find_table_offset("MVAR", &tables)?; 
+    let vhea_offset = find_table_offset("vhea", &tables)?; 
+    let vmtx_offset = find_table_offset("vmtx", &tables)?; 
+    let vorg_offset = find_table_offset("VORG", &tables)?; 
+
+    //The same text pasted from different sources measures the same once it is normalized,
+    //letters without a glyph of their own fall back to their base letter and combining marks
+    let normalization = normalize::parse_normalization(&shaping.normalization)?;
+    input_string = normalize::normalize(&input_string, normalization);
+    input_string = normalize::decompose_missing(&mut file, cmap_offset, &input_string)?;
+
+    //Get the necessary info from the offsets
+    let upem: u16 = parse_head(&mut file, head_offset)?; 
+    //A named instance supplies a value for every axis, the --axis values given come after it and win
+    if let Some(instance_name) = &input_data.variation_input.instance {
+        let mut instance_settings = fvar::find_named_instance(&mut file, fvar_offset, name_offset, instance_name)?;
+        instance_settings.append(&mut axis_settings);
+        axis_settings = instance_settings;
+    }
+    let instance = variations::read_instance(&mut file, fvar_offset, avar_offset, gdef_offset, &axis_settings)?;
+    let mut hhea: HheaMetrics = parse_hhea(&mut file, hhea_offset)?;
+    let mut os2 = os2::parse_os2(&mut file, os2_offset)?;
+    //The vertical metrics of a variable font move with the instance by their MVAR deltas
+    if mvar_offset != 0 && !instance.is_default() {
+        let coords = &instance.coords;
+        mvar::vary(&mut file, mvar_offset, "hasc", coords, &mut hhea.ascent)?;
+        mvar::vary(&mut file, mvar_offset, "hdsc", coords, &mut hhea.descent)?;
+        mvar::vary(&mut file, mvar_offset, "hlgp", coords, &mut hhea.line_gap)?;
+        mvar::vary(&mut file, mvar_offset, "tasc", coords, &mut os2.typo_ascender)?;
+        mvar::vary(&mut file, mvar_offset, "tdsc", coords, &mut os2.typo_descender)?;
+        mvar::vary(&mut file, mvar_offset, "tlgp", coords, &mut os2.typo_line_gap)?;
+        if let Some(x_height) = os2.x_height.as_mut() {
+            mvar::vary(&mut file, mvar_offset, "xhgt", coords, x_height)?;
+        }
+        if let Some(cap_height) = os2.cap_height.as_mut() {
+            mvar::vary(&mut file, mvar_offset, "cpht", coords, cap_height)?;
+        }
+    }
+    //Fonts without an outline for 'H', like many Arabic, Hebrew and Indic fonts, fall back to the OS/2 sCapHeight
+    let cap_font_height = glyf::get_cap_height(&mut file, cmap_offset, glyf_offset, loca_offset, head_offset, gvar_offset, &instance)?
+        .filter(|&h| h > 0)
+        .or(os2.cap_height.filter(|&h| h > 0))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "The font has no outline for 'H' and no OS/2 cap height to scale the text by"))?;
+
+    //Debug Print
+    println!("UPEM = {:?}", upem);
+    println!("Cap height of H: {:?}", cap_font_height);
+    println!(
+        "Vertical metrics: ascender {}, descender {}, line gap {}, OS/2 cap height {:?}, x-height {:?}",
+        hhea.ascent, hhea.descent, hhea.line_gap, os2.cap_height, os2.x_height
+    );
+
+    //The font size in points that gives the requested cap height, size dependent tables (trak) need it
+    let scale_factor = (cap_size * 72.0) / (cap_font_height as f32 * 25.4);
+    let font_pts = scale_factor * upem as f32;
+    //mm per font unit, every export uses this same factor so the outlines match the printed width
+    let mm_per_unit = (font_pts * 25.4) / (upem as f32 * 72.0);
+
+    //The text is shaped and measured, with --max-width it is wrapped at the line break opportunities that fit
+    //and shaped again as the lines it was wrapped into
+    let shaping_tables = ShapingTables {
+        cmap_offset, glyf_offset, loca_offset, head_offset, hmtx_offset, maxp_offset, kern_offset, gpos_offset,
+        gsub_offset, kerx_offset, ankr_offset, trak_offset, morx_offset, hvar_offset, gvar_offset, gdef_offset,
+    };
+    let mut shaped = shape_text(&mut file, &shaping_tables, &input_string, &selection, &instance, hhea.num_h_metrics, font_pts)?;
+    if let Some(max_width) = input_data.layout_input.max_width {
+        if selection.vertical {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "--max-width wraps horizontal text, it can't be used with --vertical"));
+        }
+        let widths = wrap::character_widths(input_string.chars().count(), &shaped.clusters, &shaped.advance_widths, &shaped.adjustments);
+        //The hyphen is measured like any other text, with the font's glyph for it
+        let patterns = input_data.layout_input.hyphenate.as_deref().map(hyphenate::load_patterns).transpose()?;
+        let hyphenation = match &patterns {
+            Some(patterns) => {
+                let hyphen = shape_text(&mut file, &shaping_tables, "-", &selection, &instance, hhea.num_h_metrics, font_pts)?;
+                let hyphen_width = wrap::character_widths(1, &hyphen.clusters, &hyphen.advance_widths, &hyphen.adjustments)[0];
+                println!("Debug: Hyphen width: {}", hyphen_width);
+                Some(wrap::Hyphenation { patterns, hyphen_width })
+            }
+            None => None,
+        };
+        let wrapped = wrap::wrap_text(&input_string, &widths, (max_width / mm_per_unit).floor() as i32, hyphenation.as_ref());
+        if wrapped != input_string {
+            input_string = wrapped;
+            shaped = shape_text(&mut file, &shaping_tables, &input_string, &selection, &instance, hhea.num_h_metrics, font_pts)?;
+        }
+    }
+    let ShapedText { glyph_indices, clusters, advance_widths, adjustments, line_ranges } = shaped;
+    //Calc the length of every line from its advances and kerning, the widest line is the width of the text
+    let line_widths_mm: Vec<f32> = line_ranges
+        .iter()
+        .map(|line| {
+            let total_width: i32 = advance_widths[line.clone()].iter().map(|&w| w as i32).sum();
+            let kerning: i32 = adjustments[line.clone()].iter().map(|a| a.x_advance).sum();
+            (total_width + kerning) as f32 * mm_per_unit
+        })
+        .collect();
+    let width_mm = line_widths_mm.iter().copied().fold(0.0, f32::max);
+    let outline_sources = OutlineSources { glyf_offset, loca_offset, head_offset, gvar_offset };
+
+    //Baselines are ascender - descender + line gap apart, from the OS/2 typo metrics when the font asks for them
+    //(or has no hhea metrics) and from hhea otherwise, unless --leading gives the distance in mm
+    let (ascent, descent, line_gap) = if os2.use_typo_metrics || (hhea.ascent == 0 && hhea.descent == 0) {
+        (os2.typo_ascender, os2.typo_descender, os2.typo_line_gap)
+    } else {
+        (hhea.ascent, hhea.descent, hhea.line_gap)
+    };
+    let line_spacing = match input_data.layout_input.leading {
+        Some(leading) => (leading / mm_per_unit).round() as i32,
+        None => ascent as i32 - descent as i32 + line_gap as i32,
+    };
+    let line_spacing_mm = line_spacing as f32 * mm_per_unit;
+    let extra_lines_mm = (line_ranges.len() - 1) as f32 * line_spacing_mm;
+    //Text of several lines is printed with its line breaks escaped, so the summary stays on one line
+    let shown_text = if line_ranges.len() > 1 { format!("{:?}", input_string) } else { input_string.clone() };
+
+    //The pen positions of the glyphs and the box around the text: its width, and the top and bottom in mm
+    let (positioned, box_width_mm, box_top_mm, box_bottom_mm) = if selection.vertical {
+        //Columns stack the advance heights from vmtx, fonts without vertical metrics
+        //use the distance between ascender and descender for every glyph
+        let vertical_metrics = if vhea_offset != 0 && vmtx_offset != 0 {
+            let num_v_metrics = vmtx::parse_vhea(&mut file, vhea_offset)?;
+            vmtx::parse_vmtx(&mut file, &glyph_indices, num_v_metrics, vmtx_offset)?
+        } else {
+            let advance_height = (hhea.ascent as i32 - hhea.descent as i32).max(0) as u16;
+            vec![vmtx::VerticalMetric { advance_height, top_side_bearing: 0 }; glyph_indices.len()]
+        };
+        let advance_heights: Vec<u16> = vertical_metrics.iter().map(|m| m.advance_height).collect();
+
+        //The vertical origin comes from VORG in CFF fonts, TrueType glyphs have it
+        //their top side bearing above the top of the outline
+        let mut origins = Vec::new();
+        for (&glyph_index, metric) in glyph_indices.iter().zip(&vertical_metrics) {
+            let origin = if vorg_offset != 0 {
+                vorg::vertical_origin(&mut file, vorg_offset, glyph_index)? as i32
+            } else {
+                let contours = glyf::get_instance_outline(&mut file, glyf_offset, loca_offset, head_offset, gvar_offset, &instance, glyph_index)?;
+                match glyf::get_glyph_bounds(&contours) {
+                    Some((_, _, _, y_max)) if vhea_offset != 0 && vmtx_offset != 0 => y_max.round() as i32 + metric.top_side_bearing as i32,
+                    _ => hhea.ascent as i32,
+                }
+            };
+            origins.push(origin);
+        }
+
+        //Every line is a column, the tallest one is the height of the text
+        let height_mm = line_ranges
+            .iter()
+            .map(|line| advance_heights[line.clone()].iter().map(|&h| h as u32).sum::<u32>() as f32 * mm_per_unit)
+            .fold(0.0, f32::max);
+        if line_ranges.len() > 1 {
+            println!("Lines: {}, column spacing {}mm", line_ranges.len(), line_spacing_mm);
+        }
+        println!("The text: {} is {}mm high in a vertical column, with capital size {}mm", shown_text, height_mm, cap_size);
+        let positioned = layout::position_columns(&glyph_indices, &advance_widths, &advance_heights, &origins, upem as i32, &line_ranges, line_spacing);
+        (positioned, upem as f32 * mm_per_unit + extra_lines_mm, 0.0, -height_mm)
+    } else {
+        if line_ranges.len() > 1 || input_data.layout_input.max_width.is_some() {
+            for (k, ((_, line), line_width_mm)) in layout::split_lines(&input_string).iter().zip(&line_widths_mm).enumerate() {
+                println!("Line {}: {:?} is {}mm wide", k + 1, line, line_width_mm);
+            }
+            println!("Lines: {}, baseline to baseline {}mm", line_ranges.len(), line_spacing_mm);
+        }
+        let block_height_mm = (ascent as i32 - descent as i32) as f32 * mm_per_unit + extra_lines_mm;
+        println!("The text: {} is {}mm wide, with capital size {}mm", shown_text, width_mm, cap_size);
+        println!("Block height: {}mm", block_height_mm);
+        let positioned = layout::position_lines(&glyph_indices, &advance_widths, &adjustments, &line_ranges, line_spacing);
+        (positioned, width_mm, ascent as f32 * mm_per_unit, descent as f32 * mm_per_unit - extra_lines_mm)
+    };
 
+    //Exports place every glyph outline at its pen position and scale it to mm
+    if !matches!(args.cmd, Commands::Set{..}) {
+        let glyphs = place_glyph_outlines(&mut file, &positioned, &outline_sources, &instance, mm_per_unit)?;
+
+        match args.cmd {
+            Commands::Svg{output, ..} => {
+                svg::write_svg(&output, &glyphs, box_width_mm, box_top_mm, box_bottom_mm)?;
+            }
+            Commands::Dxf{output, layer, tolerance, ..} => {
+                dxf::write_dxf(&output, &glyphs, &layer, tolerance)?;
+            }
+            Commands::Hpgl{output, tolerance, ..} => {
+                toolpath::write_hpgl(&output, &glyphs, tolerance)?;
+            }
+            Commands::Gcode{output, tolerance, feed, plunge_feed, safe_z, cut_depth, ..} => {
+                let settings = toolpath::GcodeSettings { feed, plunge_feed, safe_z, cut_depth };
+                toolpath::write_gcode(&output, &glyphs, tolerance, &settings)?;
+            }
+            Commands::Report{tolerance, ..} => {
+                report::print_report(&input_string, &glyph_indices, &clusters, &glyphs, tolerance);
+            }
+            Commands::Set{..} | Commands::Info{..} => {}
+        }
+    }
+
+    Ok(())
+}
+
+//A text shaped into glyphs in visual order, with the glyph range of every line
+struct ShapedText {
+    glyph_indices: Vec<u32>,
+    //The index of the first character of every glyph's cluster in the text
+    clusters: Vec<usize>,
+    advance_widths: Vec<u16>,
+    adjustments: Vec<layout::GlyphAdjustment>,
+    line_ranges: Vec<Range<usize>>,
+}
+
+fn shape_text(
+    file: &mut File,
+    tables: &ShapingTables,
+    input_string: &str,
+    selection: &features::FeatureSelection,
+    instance: &variations::Instance,
+    num_h_metrics: u16,
+    font_pts: f32,
+) -> io::Result<ShapedText> {
+    //Turns the text into positioned glyphs: cmap, GSUB (or morx), the advances at the instance,
+    //and the GPOS, kerx or kern adjustments and trak tracking
+    let ShapingTables {
+        cmap_offset, glyf_offset, loca_offset, head_offset, hmtx_offset, maxp_offset, kern_offset, gpos_offset,
+        gsub_offset, kerx_offset, ankr_offset, trak_offset, morx_offset, hvar_offset, gvar_offset, gdef_offset,
+    } = *tables;
+    //Every line is split into runs of one direction in visual order, each run is shaped in logical order
+    //and the glyphs of right-to-left runs are reversed, so every table sees the glyphs the way they are printed
+    let mut glyph_indices: Vec<u32> = Vec::new();
+    //Every glyph starts as its own cluster, GSUB may merge (ligatures) or split them
+    let mut clusters: Vec<usize> = Vec::new();
+    let mut glyph_runs: Vec<bidi::GlyphRun> = Vec::new();
+    //Runs of a joining script (Arabic, Syriac) use that script's lookups and the joining form of every letter
+    let mut run_selections: Vec<features::FeatureSelection> = Vec::new();
+    //The glyphs of every line, the line breaks themselves get no glyph
+    let mut line_ranges: Vec<Range<usize>> = Vec::new();
+    //Only the AAT lookup tables of morx and kerx need the glyph count
+    let num_glyphs = if morx_offset != 0 || kerx_offset != 0 { parse_maxp(file, maxp_offset)? } else { 0 };
+    for (line_start, line) in layout::split_lines(input_string) {
+        let first_glyph = glyph_indices.len();
+        for run in bidi::visual_runs(line) {
+            let mut run_selection = selection.clone();
+            let mut forms = Vec::new();
+            if let Some(script) = joining::joining_script(&run.text) {
+                run_selection.script = script.to_string();
+                forms = joining::joining_forms(&run.text);
+            }
+            //Indic runs are reordered by syllable and get their conjuncts from the Indic features
+            let indic_script = indic::indic_script(&run.text).filter(|_| gsub_offset != 0);
+            if let Some(script) = indic_script {
+                run_selection.script = indic::script_tag(file, gsub_offset, script)?.to_string();
+            }
+            let mut run_glyphs: Vec<u32> = cmap::parse_cmap(file, cmap_offset, &run.text)?;
+            let mut run_clusters: Vec<usize> = (0..run_glyphs.len()).collect();
+            //Joining forms and syllables are found per character, which needs a glyph for every character
+            let one_glyph_per_char = run_glyphs.len() == run.text.chars().count();
+            if !one_glyph_per_char {
+                forms.clear();
+            }
+            if let Some(script) = indic_script.filter(|_| one_glyph_per_char) {
+                (run_glyphs, run_clusters) = indic::shape_indic(file, gsub_offset, gdef_offset, &run_glyphs, &run.text, script, &run_selection)?;
+            } else if gsub_offset != 0 {
+                (run_glyphs, run_clusters) = gsub::apply_gsub(file, gsub_offset, gdef_offset, &run_glyphs, &run_selection, &forms)?;
+            } else if morx_offset != 0 {
+                //Apple fonts without GSUB do their ligatures and contextual forms in morx
+                (run_glyphs, run_clusters) = morx::apply_morx(file, morx_offset, &run_glyphs, num_glyphs)?;
+            }
+            if run.rtl {
+                run_glyphs.reverse();
+                run_clusters.reverse();
+            }
+            let start = glyph_indices.len();
+            glyph_indices.extend(run_glyphs);
+            clusters.extend(run_clusters.iter().map(|c| c + line_start + run.start));
+            glyph_runs.push((start..glyph_indices.len(), run.rtl));
+            run_selections.push(run_selection);
+        }
+        line_ranges.push(first_glyph..glyph_indices.len());
+    }
+    let mut advance_widths: Vec<u16> = hmtx::parse_hmtx(file, &glyph_indices, num_h_metrics, hmtx_offset, maxp_offset)?;
+    //Variable fonts change the advances with the instance, from HVAR or else from the gvar phantom points
+    if !instance.is_default() && (hvar_offset != 0 || gvar_offset != 0) {
+        for (advance_width, &glyph_index) in advance_widths.iter_mut().zip(&glyph_indices) {
+            let delta = if hvar_offset != 0 {
+                hvar::advance_delta(file, hvar_offset, glyph_index, &instance.coords)?
+            } else {
+                gvar::advance_delta(file, gvar_offset, glyf_offset, loca_offset, head_offset, glyph_index, &instance.coords)?
+            };
+            println!("Debug: Glyph Index: {}, Advance Width delta at instance: {}", glyph_index, delta);
+            *advance_width = (*advance_width as f32 + delta).round().max(0.0) as u16;
+        }
+    }
+    //Combining marks (GDEF class 3) sit on the glyph before them and add nothing to the width
+    let glyph_classes = gdef::glyph_classes(file, gdef_offset, &glyph_indices)?;
+    for ((advance_width, &class), &glyph_index) in advance_widths.iter_mut().zip(&glyph_classes).zip(&glyph_indices) {
+        if class == gdef::MARK && *advance_width != 0 {
+            println!("Debug: Glyph Index: {}, mark advance {} set to 0", glyph_index, advance_width);
+            *advance_width = 0;
+        }
+    }
+    //Kerning comes from the GPOS kern feature when the font has one,
+    //otherwise from the Apple kerx table or the legacy kern table.
+    //All of these adjust horizontal advances, vertical text doesn't use them.
+    let horizontal = !selection.vertical;
+    let mut adjustments = vec![layout::GlyphAdjustment::default(); glyph_indices.len()];
+    //Whether every run is kerned by GPOS, with the script and language its GPOS lookups are looked up with
+    let mut gpos_kerning = Vec::with_capacity(run_selections.len());
+    for run_selection in &run_selections {
+        gpos_kerning.push(gpos_offset != 0 && gpos::has_kerning(file, gpos_offset, run_selection)?);
+    }
+    if gpos_offset != 0 && horizontal {
+        println!("GPOS table found at offset: {}", gpos_offset);
+        //GPOS works in logical order, right-to-left runs are turned back for it
+        for ((range, rtl), run_selection) in glyph_runs.iter().zip(&run_selections) {
+            if *rtl {
+                let logical: Vec<u32> = glyph_indices[range.clone()].iter().rev().copied().collect();
+                let logical_advances: Vec<u16> = advance_widths[range.clone()].iter().rev().copied().collect();
+                let logical_classes: Vec<u16> = glyph_classes[range.clone()].iter().rev().copied().collect();
+                let run = gpos::GposRun { glyph_indices: &logical, advance_widths: &logical_advances, classes: &logical_classes, gdef_offset, rtl: true };
+                let run_adjustments = gpos::apply_gpos(file, gpos_offset, &run, run_selection, instance)?;
+                adjustments[range.clone()].copy_from_slice(&bidi::visual_adjustments(&run_adjustments));
+            } else {
+                let run = gpos::GposRun {
+                    glyph_indices: &glyph_indices[range.clone()],
+                    advance_widths: &advance_widths[range.clone()],
+                    classes: &glyph_classes[range.clone()],
+                    gdef_offset,
+                    rtl: false,
+                };
+                let run_adjustments = gpos::apply_gpos(file, gpos_offset, &run, run_selection, instance)?;
+                adjustments[range.clone()].copy_from_slice(&run_adjustments);
+            }
+        }
+    }
+    //Runs without GPOS kerning get theirs from kerx or kern. Pairs are kerned within a run,
+    //never across a line break or between runs of different directions.
+    let unkerned_runs: Vec<Range<usize>> = glyph_runs
+        .iter()
+        .zip(&gpos_kerning)
+        .filter(|(_, &gpos_kerned)| !gpos_kerned)
+        .map(|((range, _), _)| range.clone())
+        .collect();
+    if horizontal && !unkerned_runs.is_empty() && kerx_offset != 0 && selection.is_enabled("kern", true) {
+        println!("Kerx table found at offset: {}", kerx_offset);
+        let sources = kerx::AttachmentSources { glyf_offset, loca_offset, head_offset, ankr_offset };
+        for run in &unkerned_runs {
+            kerx::apply_kerx(file, kerx_offset, &glyph_indices[run.clone()], &advance_widths[run.clone()], num_glyphs, &sources, &mut adjustments[run.clone()])?;
+        }
+    } else if horizontal && !unkerned_runs.is_empty() && kern_offset != 0 && selection.is_enabled("kern", true) {
+        println!("Kern table found at offset: {}", kern_offset);
+        for run in &unkerned_runs {
+            let kerning = kern::parse_kern_table(file, &glyph_indices[run.clone()], kern_offset)?;
+            for (adjustment, value) in adjustments[run.clone()].iter_mut().zip(kerning) {
+                adjustment.x_advance += value;
+            }
+        }
+    }
+
+    //Apple fonts may add size dependent tracking to every advance
+    if trak_offset != 0 && horizontal {
+        let tracking = trak::get_tracking(file, trak_offset, font_pts)?;
+        for adjustment in adjustments.iter_mut() {
+            adjustment.x_advance += tracking;
+        }
+    }
+
+    Ok(ShapedText { glyph_indices, clusters, advance_widths, adjustments, line_ranges })
+}
+
+fn read_table_directory(file: &mut File) -> io::Result<Vec<TableRecord>> {
     //Read The first tables of the font
     let _scaler_type = file.read_u32::<BigEndian>()?;
     let num_tables = file.read_u16::<BigEndian>()?;
@@ -84,57 +681,48 @@ fn main() -> io::Result<()> {
             length,
         });
     }
-
-    // Find necessary table offsets
-    let cmap_offset = find_table_offset("cmap", &tables)?;
-    let glyf_offset = find_table_offset("glyf", &tables)?;
-    let loca_offset = find_table_offset("loca", &tables)?;
-    let head_offset = find_table_offset("head", &tables)?; 
-    let hmtx_offset = find_table_offset("hmtx", &tables)?; 
-    let maxp_offset = find_table_offset("maxp", &tables)?; 
-    let hhea_offset = find_table_offset("hhea", &tables)?; 
-    let kern_offset = find_table_offset("kern", &tables)?; 
-
-    //Get the necessary info from the offsets
-    let upem: u16 = parse_head(&mut file, head_offset)?; 
-    let glyph_indices: Vec<u32> = cmap::parse_cmap(&mut file, cmap_offset, &input_string)?;
-    let num_h_metrics: u16 = parse_hhea(&mut file, hhea_offset)?;
-    let total_width: u32 = hmtx::parse_hmtx(&mut file, &glyph_indices, num_h_metrics, hmtx_offset, hhea_offset, maxp_offset)?;
-    let cap_font_height = glyf::get_cap_height(&mut file, cmap_offset, glyf_offset, loca_offset, head_offset);
-
-    //Debug Print
-    println!("UPEM = {:?}", upem);
-    println!("Cap height of H: {:?}", cap_font_height);
-    
-    //Check if kern table is present ad apply if it is found
-    let mut kerning: i32 = 0;
-    if kern_offset != 0 {
-        println!("Kern table found at offset: {}", kern_offset);
-        kerning = kern::parse_kern_table(&mut file, &glyph_indices, kern_offset)?;
-    }
-     
-    //Calc the length from cap size with the right conversions to mm
-    let total_kerning = total_width as i32 + kerning;
-    let scale_factor = (cap_size as f32 * 72.0) / (cap_font_height.unwrap() as f32 * 25.4);
-    let font_pts = scale_factor * upem as f32;
-    let width_mm = (total_kerning as f32 * font_pts * 25.4) / (upem as f32 * 72.0);
-    println!("The text: {} is {}mm wide, with capital size {}mm", input_string, width_mm, cap_size);
-
-    Ok(())
+    Ok(tables)
 }
 
-fn find_table_offset (table_name: &str, tables: &Vec<TableRecord> ) -> io::Result<u32> {
+fn find_table_offset (table_name: &str, tables: &[TableRecord] ) -> io::Result<u32> {
     //Find table offset from tag
     let offset_name = tables.iter().find(|t| t.tag == table_name);
 
-    if offset_name == None {
-        println!("{} Table, is not found in this file", table_name);
-        Ok(0)
-    } else {
-        Ok(offset_name.unwrap().offset.into())
+    match offset_name {
+        Some(table) => Ok(table.offset),
+        None => {
+            println!("{} Table, is not found in this file", table_name);
+            Ok(0)
+        }
     }
 }
 
+fn place_glyph_outlines(
+    file: &mut File,
+    positioned: &[layout::PositionedGlyph],
+    sources: &OutlineSources,
+    instance: &variations::Instance,
+    mm_per_unit: f32,
+) -> io::Result<Vec<Vec<Vec<outline::Segment>>>> {
+    //Returns the contours of every glyph as segments in mm, baseline at y = 0,
+    //variable fonts are drawn at the instance
+    let mut glyphs = Vec::new();
+    for glyph in positioned {
+        let contours = glyf::get_instance_outline(file, sources.glyf_offset, sources.loca_offset, sources.head_offset, sources.gvar_offset, instance, glyph.glyph_index)?;
+        let placed = contours
+            .iter()
+            .map(|contour| {
+                outline::contour_segments(contour)
+                    .iter()
+                    .map(|s| s.map(|(x, y)| ((x + glyph.x as f32) * mm_per_unit, (y + glyph.y as f32) * mm_per_unit)))
+                    .collect()
+            })
+            .collect();
+        glyphs.push(placed);
+    }
+    Ok(glyphs)
+}
+
 fn parse_head(file: &mut File, head_offset: u32) -> io::Result<u16> {
     // Seek to the units per em (UPEM) value (offset 18 bytes into the table)
     file.seek(SeekFrom::Start(head_offset as u64 + 18))?;
@@ -142,18 +730,24 @@ fn parse_head(file: &mut File, head_offset: u32) -> io::Result<u16> {
     Ok(upem)
 }
 
-fn parse_hhea(file: &mut File, hhea_offset: u32) -> io::Result<u16> {
+fn parse_maxp(file: &mut File, maxp_offset: u32) -> io::Result<u16> {
+    // Skip the version (4 bytes) and read numGlyphs (2 bytes)
+    file.seek(SeekFrom::Start(maxp_offset as u64 + 4))?;
+    let num_glyphs = file.read_u16::<BigEndian>()?;
+    Ok(num_glyphs)
+}
+
+fn parse_hhea(file: &mut File, hhea_offset: u32) -> io::Result<HheaMetrics> {
     // Seek to the hhea table
     file.seek(SeekFrom::Start(hhea_offset as u64))?;
     // Read the hhea table metrics
     let _version = file.read_u32::<BigEndian>()?;
-    let _ascent = file.read_i16::<BigEndian>()?;
-    let _descent = file.read_i16::<BigEndian>()?;
-    let _line_gap = file.read_i16::<BigEndian>()?;
+    let ascent = file.read_i16::<BigEndian>()?;
+    let descent = file.read_i16::<BigEndian>()?;
+    let line_gap = file.read_i16::<BigEndian>()?;
+    // numberOfHMetrics is the last field of the 36 byte table
+    file.seek(SeekFrom::Start(hhea_offset as u64 + 34))?;
     let num_h_metrics = file.read_u16::<BigEndian>()?;
 
-    Ok(num_h_metrics)
+    Ok(HheaMetrics { ascent, descent, line_gap, num_h_metrics })
 }
-
-
-